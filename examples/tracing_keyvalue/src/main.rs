@@ -13,21 +13,21 @@ fn main() {
         .with_module_path(true)
         .with_span_name(true)
         .layer()
-        // Only apply this layer if the target is NOT pyo3_logger
+        // Only apply this layer if the target is NOT a Python logger
         .with_filter(filter_fn(|metadata| {
-            metadata.target() != "pyo3_pylogger" && metadata.target() != "tracing_keyvalue"
+            !metadata.target().starts_with("tracing_keyvalue")
         }));
 
-    // Layer specifically for pyo3_logger targets - omits location and module_path
+    // Layer specifically for Python logger targets - omits location and module_path
     let pyo3_layer = tracing_logfmt::builder()
         .with_location(false) // No location
         .with_target(false)
         .with_module_path(false) // No module path
         .with_span_name(true)
         .layer()
-        // Only apply this layer if the target IS pyo3_logger
+        // Only apply this layer if the target IS a Python logger
         .with_filter(filter_fn(|metadata| {
-            metadata.target() == "pyo3_pylogger" || metadata.target() == "tracing_keyvalue"
+            metadata.target().starts_with("tracing_keyvalue")
         }));
 
     tracing_subscriber::registry()
@@ -0,0 +1,270 @@
+//! `tracing` callsites created at runtime for Python records.
+//!
+//! `tracing` expects events to come from `'static` callsites declared by its macros, whose metadata holds the
+//! target, level and source location. Python records only know these at runtime, so a callsite is created
+//! (and leaked) the first time a combination of them is seen, then reused for every following record.
+//!
+//! Records logged by the same statement with different `extra` keys have different field names, and
+//! need their own callsite. To keep the leaked memory bounded when keys vary between records (e.g.
+//! `extra={f"k{i}": i}`), a location gets at most [MAX_FIELD_SETS] callsites, and records with more than
+//! [MAX_EXTRA_FIELDS] key-values or beyond that limit record all of them as a single map field, see
+//! [try_callsite].
+
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Mutex, OnceLock, RwLock};
+
+use tracing_core::callsite::{Callsite, Identifier};
+use tracing_core::field::{Field, FieldSet, Value};
+use tracing_core::metadata::Kind;
+use tracing_core::subscriber::Interest;
+use tracing_core::{Event, Level, Metadata};
+
+const INTEREST_NEVER: u8 = 0;
+const INTEREST_SOMETIMES: u8 = 1;
+const INTEREST_ALWAYS: u8 = 2;
+
+/// The maximum number of callsites for a [Location], beyond which [try_callsite] returns `None`.
+const MAX_FIELD_SETS: usize = 8;
+
+/// The maximum number of fields besides `record::FIELDS` that [try_callsite] creates a callsite for.
+pub(crate) const MAX_EXTRA_FIELDS: usize = 16;
+
+/// The field recording the key-values of records for which [try_callsite] returns `None`, as a map.
+#[cfg(feature = "tracing-kv")]
+pub(crate) const OVERFLOW_FIELD: &str = "extra";
+
+/// Where, and at which level, a Python record was logged.
+#[derive(Debug)]
+pub(crate) struct Location<'a> {
+    pub target: &'a str,
+    pub file: &'a str,
+    pub line: u32,
+    pub module_path: &'a str,
+    pub level: Level,
+}
+
+impl Location<'_> {
+    fn hash(&self, state: &RandomState) -> u64 {
+        state.hash_one((
+            self.target,
+            self.file,
+            self.line,
+            self.module_path,
+            self.level,
+        ))
+    }
+
+    fn matches(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.target() == self.target
+            && metadata.file() == Some(self.file)
+            && metadata.line() == Some(self.line)
+            && metadata.module_path() == Some(self.module_path)
+            && *metadata.level() == self.level
+    }
+}
+
+/// A callsite for the Python records sharing a [Location] and a set of field names.
+pub(crate) struct PyCallsite {
+    metadata: OnceLock<Metadata<'static>>,
+    /// The fields of the metadata, in order.
+    fields: OnceLock<Box<[Field]>>,
+    interest: AtomicU8,
+}
+
+impl Callsite for PyCallsite {
+    fn set_interest(&self, interest: Interest) {
+        let interest = if interest.is_never() {
+            INTEREST_NEVER
+        } else if interest.is_always() {
+            INTEREST_ALWAYS
+        } else {
+            INTEREST_SOMETIMES
+        };
+        self.interest.store(interest, Ordering::Relaxed);
+    }
+
+    fn metadata(&self) -> &Metadata<'_> {
+        self.metadata
+            .get()
+            .expect("callsites are registered after their metadata is set")
+    }
+}
+
+impl PyCallsite {
+    fn metadata(&'static self) -> &'static Metadata<'static> {
+        self.metadata
+            .get()
+            .expect("callsites are registered after their metadata is set")
+    }
+
+    /// Whether the current subscriber is interested in events from this callsite.
+    pub(crate) fn is_enabled(&'static self) -> bool {
+        match self.interest.load(Ordering::Relaxed) {
+            INTEREST_NEVER => false,
+            INTEREST_ALWAYS => true,
+            _ => {
+                tracing_core::dispatcher::get_default(|dispatch| dispatch.enabled(self.metadata()))
+            }
+        }
+    }
+
+    /// Dispatches an event with one value per field, in the order the field names were given.
+    pub(crate) fn dispatch(&'static self, values: &[Option<&dyn Value>]) {
+        // `FieldSet::value_set` takes arrays of a length known at compile time: one for each number of fields
+        // a callsite can have, from `record::FIELDS` alone to `record::FIELDS` and `MAX_EXTRA_FIELDS` more.
+        const _: () = assert!(crate::record::FIELDS.len() == 9 && MAX_EXTRA_FIELDS == 16);
+        macro_rules! dispatch {
+            ($($len:literal)*) => {
+                match values.len() {
+                    $($len => self.dispatch_array::<$len>(values),)*
+                    len => debug_assert!(false, "no callsite has {len} fields"),
+                }
+            };
+        }
+        dispatch!(9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25);
+    }
+
+    fn dispatch_array<const N: usize>(&'static self, values: &[Option<&dyn Value>]) {
+        let fields = self
+            .fields
+            .get()
+            .expect("callsites are registered after their metadata is set");
+        let values: [_; N] = std::array::from_fn(|i| (&fields[i], values[i]));
+        Event::dispatch(
+            self.metadata(),
+            &self.metadata().fields().value_set(&values),
+        );
+    }
+}
+
+/// Returns the callsite for `location` with the given field names, creating and registering it if needed.
+pub(crate) fn callsite(location: &Location<'_>, fields: &[&str]) -> &'static PyCallsite {
+    get_or_register(location, fields, false).expect("uncapped callsites are always created")
+}
+
+/// Returns the callsite for `location` with the given field names like [callsite], unless it would be a new
+/// callsite beyond the [MAX_FIELD_SETS] of `location` or has more than [MAX_EXTRA_FIELDS] extra fields.
+#[cfg(feature = "tracing-kv")]
+pub(crate) fn try_callsite(
+    location: &Location<'_>,
+    fields: &[&str],
+) -> Option<&'static PyCallsite> {
+    if fields.len() > crate::record::FIELDS.len() + MAX_EXTRA_FIELDS {
+        return None;
+    }
+    get_or_register(location, fields, true)
+}
+
+fn get_or_register(
+    location: &Location<'_>,
+    fields: &[&str],
+    capped: bool,
+) -> Option<&'static PyCallsite> {
+    let callsites = callsites();
+    let hash = location.hash(&callsites.hasher);
+    // Callsites are found by comparing their metadata, so nothing is copied for records of known callsites.
+    let find = |candidates: &[&'static PyCallsite]| {
+        candidates.iter().copied().find(|callsite| {
+            let metadata = callsite.metadata();
+            location.matches(metadata)
+                && metadata.fields().len() == fields.len()
+                && metadata
+                    .fields()
+                    .iter()
+                    .zip(fields)
+                    .all(|(field, name)| field.name() == *name)
+        })
+    };
+
+    let by_location = callsites.map.read().unwrap_or_else(|e| e.into_inner());
+    if let Some(callsite) = by_location.get(&hash).and_then(|c| find(c)) {
+        return Some(callsite);
+    }
+    drop(by_location);
+
+    let mut by_location = callsites.map.write().unwrap_or_else(|e| e.into_inner());
+    let candidates = by_location.entry(hash).or_default();
+    if let Some(callsite) = find(candidates) {
+        return Some(callsite);
+    }
+    let at_location = candidates
+        .iter()
+        .filter(|callsite| location.matches(callsite.metadata()))
+        .count();
+    if capped && at_location >= MAX_FIELD_SETS {
+        return None;
+    }
+
+    let callsite: &'static PyCallsite = Box::leak(Box::new(PyCallsite {
+        metadata: OnceLock::new(),
+        fields: OnceLock::new(),
+        interest: AtomicU8::new(INTEREST_SOMETIMES),
+    }));
+    let _ = callsite.metadata.set(Metadata::new(
+        "event from Python",
+        intern(location.target),
+        location.level,
+        Some(intern(location.file)),
+        Some(location.line),
+        Some(intern(location.module_path)),
+        FieldSet::new(intern_fields(fields), Identifier(callsite)),
+        Kind::EVENT,
+    ));
+    let _ = callsite
+        .fields
+        .set(callsite.metadata().fields().iter().collect());
+    candidates.push(callsite);
+    drop(by_location);
+
+    // Registering calls into the subscribers, which must not happen while holding the lock.
+    tracing_core::callsite::register(callsite);
+    Some(callsite)
+}
+
+/// The callsites created so far, by the hash of their [Location].
+struct Callsites {
+    hasher: RandomState,
+    map: RwLock<HashMap<u64, Vec<&'static PyCallsite>>>,
+}
+
+fn callsites() -> &'static Callsites {
+    static CALLSITES: OnceLock<Callsites> = OnceLock::new();
+    CALLSITES.get_or_init(|| Callsites {
+        hasher: RandomState::new(),
+        map: Default::default(),
+    })
+}
+
+/// Returns a `'static` copy of `value`, leaking it the first time it is seen.
+fn intern(value: &str) -> &'static str {
+    static STRINGS: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+    let mut strings = STRINGS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    if let Some(interned) = strings.get(value) {
+        return interned;
+    }
+    let interned: &'static str = Box::leak(value.into());
+    strings.insert(interned);
+    interned
+}
+
+/// Returns a `'static` copy of a list of field names, leaking it the first time it is seen.
+fn intern_fields(fields: &[&str]) -> &'static [&'static str] {
+    static FIELDS: OnceLock<Mutex<HashSet<&'static [&'static str]>>> = OnceLock::new();
+    let mut interned_fields = FIELDS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    let fields: Vec<&'static str> = fields.iter().map(|field| intern(field)).collect();
+    if let Some(interned) = interned_fields.get(fields.as_slice()) {
+        return interned;
+    }
+    let interned: &'static [&'static str] = Box::leak(fields.into_boxed_slice());
+    interned_fields.insert(interned);
+    interned
+}
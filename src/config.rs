@@ -0,0 +1,471 @@
+//! Registration options for the Python `logging` bridge.
+//!
+//! [PyLoggerConfig] collects every behavioral option of the bridge and performs the registration.
+//! The free functions [crate::register] and [crate::setup_logging] are thin wrappers around
+//! [PyLoggerConfig::new] with all options left at their defaults.
+
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use pyo3::prelude::*;
+
+#[cfg(feature = "kv-common")]
+use crate::kv::{KvFilter, KvNamespace, KvStrategy};
+use crate::level::{LevelMapping, PyLevel};
+use crate::redact::Redaction;
+use crate::sync::LevelSync;
+use crate::{RedactRule, TargetMode, TargetRule};
+
+/// The Rust logging implementation that Python records are emitted to.
+///
+/// Each variant is only available when the matching feature is enabled, so both backends can be compiled in
+/// (e.g. when features are unified across a workspace) and the choice made at registration time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// Emit records through `log::logger()`.
+    #[cfg(feature = "log")]
+    Log,
+    /// Emit records as `tracing` events.
+    #[cfg(feature = "tracing")]
+    Tracing,
+    /// Emit records to both `log` and `tracing`.
+    #[cfg(all(feature = "log", feature = "tracing"))]
+    Both,
+}
+
+impl Default for Backend {
+    /// `Log` if the `log` feature is enabled, `Tracing` otherwise.
+    fn default() -> Self {
+        #[cfg(feature = "log")]
+        {
+            Backend::Log
+        }
+        #[cfg(not(feature = "log"))]
+        {
+            Backend::Tracing
+        }
+    }
+}
+
+/// Builder for registering the Rust host handler with Python's `logging` module.
+///
+/// # Example
+/// ```no_run
+/// pyo3_pylogger::PyLoggerConfig::new("my_application")
+///     .with_basic_config(false)
+///     .register();
+/// ```
+#[derive(Clone, Debug)]
+pub struct PyLoggerConfig {
+    target: String,
+    backend: Backend,
+    basic_config: bool,
+    #[cfg(feature = "kv-common")]
+    kv: bool,
+    #[cfg(feature = "kv-common")]
+    kv_strategy: KvStrategy,
+    #[cfg(feature = "kv-common")]
+    kv_filter: KvFilter,
+    #[cfg(feature = "kv-common")]
+    kv_namespace: KvNamespace,
+    /// The attributes of a blank `LogRecord`, detected at registration, and those set by a `Formatter`.
+    #[cfg(feature = "kv-common")]
+    kv_standard_attributes: Arc<std::collections::HashSet<String>>,
+    /// The attributes of a blank record, computed at registration for [KvStrategy::Baseline].
+    #[cfg(feature = "kv-common")]
+    kv_baseline: Arc<std::collections::HashSet<String>>,
+    stack_info: bool,
+    python_level: bool,
+    level_mapping: LevelMapping,
+    level_sync: bool,
+    level_sync_state: Arc<LevelSync>,
+    target_mode: TargetMode,
+    target_rules: Vec<TargetRule>,
+    redaction: Redaction,
+}
+
+impl PyLoggerConfig {
+    /// Creates a configuration with default options, using `target` as the Rust target of the root Python logger.
+    pub fn new(target: impl Into<String>) -> Self {
+        Self {
+            target: target.into(),
+            backend: Backend::default(),
+            basic_config: true,
+            #[cfg(feature = "kv-common")]
+            kv: true,
+            #[cfg(feature = "kv-common")]
+            kv_strategy: KvStrategy::default(),
+            #[cfg(feature = "kv-common")]
+            kv_filter: KvFilter::default(),
+            #[cfg(feature = "kv-common")]
+            kv_namespace: KvNamespace::default(),
+            #[cfg(feature = "kv-common")]
+            kv_standard_attributes: Default::default(),
+            #[cfg(feature = "kv-common")]
+            kv_baseline: Default::default(),
+            stack_info: false,
+            python_level: false,
+            level_mapping: LevelMapping::Default,
+            level_sync: false,
+            level_sync_state: Default::default(),
+            target_mode: TargetMode::Prefixed,
+            target_rules: Vec::new(),
+            redaction: Redaction::default(),
+        }
+    }
+
+    /// The Rust target that Python records are emitted under.
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// How Python logger names are turned into Rust targets. Defaults to [TargetMode::Prefixed].
+    pub fn with_target_mode(mut self, mode: TargetMode) -> Self {
+        self.target_mode = mode;
+        self
+    }
+
+    pub(crate) fn target_mode(&self) -> &TargetMode {
+        &self.target_mode
+    }
+
+    /// Adds a rule rewriting the Rust target of matching Python loggers, see [TargetRule].
+    ///
+    /// Rules are tried in the order they were added, and the first matching rule applies.
+    pub fn with_target_rule(mut self, rule: TargetRule) -> Self {
+        self.target_rules.push(rule);
+        self
+    }
+
+    pub(crate) fn target_rules(&self) -> &[TargetRule] {
+        &self.target_rules
+    }
+
+    /// Adds a rule redacting sensitive data from records, see [RedactRule].
+    ///
+    /// Rules are applied in the order they were added, to the formatted message, the exception message and
+    /// traceback, `stack_info` and `exc_text`, and to the key-values (with the `kv` or `tracing-kv` features)
+    /// before namespacing.
+    pub fn with_redact_rule(mut self, rule: RedactRule) -> Self {
+        self.redaction.rules.push(rule);
+        self
+    }
+
+    /// The text redacted data is replaced with. Defaults to `[REDACTED]`.
+    pub fn with_redaction_replacement(mut self, replacement: impl Into<String>) -> Self {
+        self.redaction.replacement = replacement.into();
+        self
+    }
+
+    pub(crate) fn redaction(&self) -> &Redaction {
+        &self.redaction
+    }
+
+    /// The Rust logging implementation records are emitted to. Defaults to [Backend::default].
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    pub(crate) fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Whether `logging.basicConfig` is patched to install a `HostHandler` when no handlers are given.
+    ///
+    /// Defaults to `true`.
+    pub fn with_basic_config(mut self, enabled: bool) -> Self {
+        self.basic_config = enabled;
+        self
+    }
+
+    /// Whether custom `LogRecord` attributes (Python's `extra`) are forwarded as key-value pairs.
+    ///
+    /// Defaults to `true`.
+    #[cfg(feature = "kv-common")]
+    pub fn with_kv(mut self, enabled: bool) -> Self {
+        self.kv = enabled;
+        self
+    }
+
+    #[cfg(feature = "kv-common")]
+    pub(crate) fn kv(&self) -> bool {
+        self.kv
+    }
+
+    /// How custom `LogRecord` attributes are told apart from the standard ones. Defaults to [KvStrategy::Trailing].
+    #[cfg(feature = "kv-common")]
+    pub fn with_kv_strategy(mut self, strategy: KvStrategy) -> Self {
+        self.kv_strategy = strategy;
+        self
+    }
+
+    #[cfg(feature = "kv-common")]
+    pub(crate) fn kv_strategy(&self) -> &KvStrategy {
+        &self.kv_strategy
+    }
+
+    /// Excludes key-values whose key matches one of `patterns`, in addition to those already excluded.
+    ///
+    /// A `*` in a pattern matches any sequence of characters, so `_*` excludes private attributes. This applies
+    /// to every [KvStrategy], e.g. to drop attributes that frameworks attach to records:
+    ///
+    /// ```no_run
+    /// pyo3_pylogger::PyLoggerConfig::new("my_application")
+    ///     .with_kv_exclude(["request", "color_message", "_*"])
+    ///     .register();
+    /// ```
+    #[cfg(feature = "kv-common")]
+    pub fn with_kv_exclude(
+        mut self,
+        patterns: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.kv_filter
+            .exclude
+            .extend(patterns.into_iter().map(Into::into));
+        self
+    }
+
+    /// Only forwards key-values whose key matches one of `patterns`, in addition to those already included.
+    ///
+    /// Patterns are matched like in [PyLoggerConfig::with_kv_exclude], and exclusions still apply. Unlike
+    /// [KvStrategy::Allowlist], keys must first be found by the configured [KvStrategy].
+    #[cfg(feature = "kv-common")]
+    pub fn with_kv_include(
+        mut self,
+        patterns: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.kv_filter
+            .include
+            .get_or_insert_with(Vec::new)
+            .extend(patterns.into_iter().map(Into::into));
+        self
+    }
+
+    #[cfg(feature = "kv-common")]
+    pub(crate) fn kv_filter(&self) -> &KvFilter {
+        &self.kv_filter
+    }
+
+    /// Where key-values from `extra` are placed. Defaults to [KvNamespace::Flat].
+    ///
+    /// Exclusions and inclusions apply to the original keys, before they are namespaced.
+    #[cfg(feature = "kv-common")]
+    pub fn with_kv_namespace(mut self, namespace: KvNamespace) -> Self {
+        self.kv_namespace = namespace;
+        self
+    }
+
+    #[cfg(feature = "kv-common")]
+    pub(crate) fn kv_namespace(&self) -> &KvNamespace {
+        &self.kv_namespace
+    }
+
+    #[cfg(feature = "kv-common")]
+    pub(crate) fn kv_standard_attributes(&self) -> &std::collections::HashSet<String> {
+        &self.kv_standard_attributes
+    }
+
+    #[cfg(feature = "kv-common")]
+    pub(crate) fn kv_baseline(&self) -> &std::collections::HashSet<String> {
+        &self.kv_baseline
+    }
+
+    /// Whether the `stack_info` and `exc_text` attributes of records are forwarded.
+    ///
+    /// They are forwarded as the `stack_info` and `exc_text` key-values with the `kv` feature and as fields
+    /// of the same names under `tracing`, or appended to the message otherwise. Defaults to `false`.
+    pub fn with_stack_info(mut self, enabled: bool) -> Self {
+        self.stack_info = enabled;
+        self
+    }
+
+    pub(crate) fn stack_info(&self) -> bool {
+        self.stack_info
+    }
+
+    /// Whether the original Python level name and number are forwarded alongside the mapped level.
+    ///
+    /// Rust has no level above `Error`, so this is the only way to tell `CRITICAL` records apart downstream.
+    /// They are forwarded as the `py.levelname` and `py.levelno` key-values with the `kv` feature and as
+    /// fields of the same names under `tracing`. `py.levelno` is the original number, even outside of the
+    /// `i32` range used for mapping levels. Defaults to `false`.
+    pub fn with_python_level(mut self, enabled: bool) -> Self {
+        self.python_level = enabled;
+        self
+    }
+
+    pub(crate) fn python_level(&self) -> bool {
+        self.python_level
+    }
+
+    /// Maps Python level numbers with a table of `(threshold, level)` pairs instead of the default thresholds.
+    ///
+    /// A level number maps to the level of the highest threshold it reaches, or to [PyLevel::Trace] if it is
+    /// below all of them. This allows custom Python levels to map to a matching Rust level:
+    ///
+    /// ```no_run
+    /// use pyo3_pylogger::{PyLevel, PyLoggerConfig};
+    ///
+    /// PyLoggerConfig::new("my_application")
+    ///     .with_level_table([
+    ///         (45, PyLevel::Error),   // AUDIT
+    ///         (40, PyLevel::Error),
+    ///         (30, PyLevel::Warning),
+    ///         (25, PyLevel::Warning), // NOTICE
+    ///         (20, PyLevel::Info),
+    ///         (10, PyLevel::Debug),
+    ///         (5, PyLevel::Trace),    // TRACE
+    ///     ])
+    ///     .register();
+    /// ```
+    pub fn with_level_table(mut self, table: impl IntoIterator<Item = (i32, PyLevel)>) -> Self {
+        self.level_mapping = LevelMapping::table(table);
+        self
+    }
+
+    /// Maps Python level numbers with a function instead of the default thresholds.
+    pub fn with_level_mapper(
+        mut self,
+        mapper: impl Fn(i32) -> PyLevel + Send + Sync + 'static,
+    ) -> Self {
+        self.level_mapping = LevelMapping::Custom(Arc::new(mapper));
+        self
+    }
+
+    /// Maps a Python level number, saturated to the `i32` range of level mappings.
+    pub(crate) fn level(&self, levelno: i64) -> PyLevel {
+        self.level_mapping.map(crate::level::saturate(levelno))
+    }
+
+    pub(crate) fn level_mapping(&self) -> &LevelMapping {
+        &self.level_mapping
+    }
+
+    /// Whether the Python root logger and `HostHandler` levels follow the Rust maximum level.
+    ///
+    /// The levels are set at registration and whenever the `HostHandler` sees that the Rust maximum level
+    /// changed; see [crate::sync_levels] for making the Rust side more verbose. Nothing is changed until a
+    /// Rust logger enables at least one level, or if the levels can't be derived from the level mapping
+    /// (i.e. with [PyLoggerConfig::with_level_mapper]). Defaults to `false`.
+    pub fn with_level_sync(mut self, enabled: bool) -> Self {
+        self.level_sync = enabled;
+        self
+    }
+
+    pub(crate) fn level_sync(&self) -> bool {
+        self.level_sync
+    }
+
+    pub(crate) fn level_sync_state(&self) -> &LevelSync {
+        &self.level_sync_state
+    }
+
+    /// Registers the host handler with the Python logging instance, attaching to the interpreter.
+    ///
+    /// # Panics
+    /// Panics if the `logging` module could not be extended.
+    pub fn register(self) {
+        Python::attach(|py| self.setup_logging(py)).unwrap();
+    }
+
+    /// Registers the host handler from within an existing pyo3 context.
+    ///
+    /// This needs to happen as early as possible to ensure logging messages arrive to the Rust consumer.
+    pub fn setup_logging(mut self, py: Python) -> PyResult<()> {
+        let logging = py.import("logging")?;
+
+        logging.setattr("host_log", wrap_pyfunction!(crate::host_log, &logging)?)?;
+
+        let mut code = format!(
+            r#"
+if "_pyo3_pylogger_levels" not in globals():
+    _pyo3_pylogger_levels = {{}}
+
+def _pyo3_pylogger_sync_level(target, level):
+    _pyo3_pylogger_levels[target] = level
+    root.setLevel(level)
+    for logger in (root, *Logger.manager.loggerDict.values()):
+        for handler in getattr(logger, "handlers", ()):
+            if getattr(handler, "pyo3_pylogger_target", None) == target:
+                handler.setLevel(level)
+
+class HostHandler(Handler):
+	pyo3_pylogger_target = {target:?}
+
+	def __init__(self, level=None):
+		if level is None:
+			level = _pyo3_pylogger_levels.get(self.pyo3_pylogger_target, 0)
+		super().__init__(level=level)
+
+	def emit(self, record: LogRecord):
+		host_log(record, {target:?})
+"#,
+            target = self.target
+        );
+
+        if self.basic_config {
+            code.push_str(
+                r#"
+oldBasicConfig = basicConfig
+def basicConfig(*pargs, **kwargs):
+    if "handlers" not in kwargs:
+        kwargs["handlers"] = [HostHandler()]
+    return oldBasicConfig(*pargs, **kwargs)
+"#,
+            );
+        }
+
+        py.run(&std::ffi::CString::new(code)?, Some(&logging.dict()), None)?;
+
+        let all = logging.index()?;
+        all.append("HostHandler")?;
+
+        #[cfg(feature = "kv-common")]
+        {
+            self.kv_standard_attributes = Arc::new(crate::kv::standard_attributes(&logging)?);
+        }
+        #[cfg(feature = "kv-common")]
+        if self.kv_strategy == KvStrategy::Baseline {
+            self.kv_baseline = Arc::new(crate::kv::baseline_attributes(&logging)?);
+        }
+
+        // A new registration starts out unsynced, even if cloned from a registered configuration.
+        self.level_sync_state = Default::default();
+        if self.level_sync {
+            crate::sync::sync(py, &self)?;
+        }
+
+        registry()
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(self.target.clone(), Arc::new(self));
+
+        Ok(())
+    }
+}
+
+fn registry() -> &'static RwLock<HashMap<String, Arc<PyLoggerConfig>>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<String, Arc<PyLoggerConfig>>>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Returns the configuration registered for `target`, or the defaults if `host_log` is called for an unknown target.
+pub(crate) fn lookup(target: &str) -> Arc<PyLoggerConfig> {
+    registry()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(target)
+        .cloned()
+        .unwrap_or_else(|| Arc::new(PyLoggerConfig::new(target)))
+}
+
+/// Returns every registered configuration.
+pub(crate) fn registered() -> Vec<Arc<PyLoggerConfig>> {
+    registry()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .values()
+        .cloned()
+        .collect()
+}
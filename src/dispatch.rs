@@ -0,0 +1,265 @@
+//! Delivery of Rust-originated records into Python's `logging` module.
+//!
+//! Rust threads must never block on the GIL while logging: the thread holding it may itself be waiting
+//! on the logging thread. Records logged from a thread that is already attached to the interpreter are
+//! handled inline; all other records are buffered and handed to Python by a background worker thread.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+
+use pyo3::prelude::*;
+use pyo3::types::PyTuple;
+
+use crate::guard;
+
+/// Maximum number of records buffered while waiting for the GIL. Records beyond this are discarded and
+/// counted, see [overflowed_records].
+const BUFFER_CAPACITY: usize = 4096;
+
+/// How long the worker waits before retrying when the interpreter cannot be attached to.
+const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long [flush] waits for the worker to deliver the buffered records, from a thread not attached to the
+/// interpreter.
+#[cfg(feature = "log")]
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
+static OVERFLOWED: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the number of Rust records discarded because the buffer of records waiting for the GIL was full.
+///
+/// A non-zero count means records are logged from threads not attached to the interpreter faster than
+/// Python handles them, or while the interpreter cannot be attached to (before initialization, or after
+/// finalization).
+pub fn overflowed_records() -> u64 {
+    OVERFLOWED.load(Ordering::Relaxed)
+}
+
+/// A record produced on the Rust side, owned so it can cross threads before reaching Python.
+pub(crate) struct PyRecord {
+    /// The Python logger name, already converted from a Rust target (`foo::bar` -> `foo.bar`).
+    pub name: String,
+    pub levelno: i32,
+    pub pathname: Option<String>,
+    pub lineno: Option<u32>,
+    pub message: String,
+    /// Attributes set on the `LogRecord`, equivalent to Python's `extra`.
+    pub extra: Vec<(String, ExtraValue)>,
+}
+
+/// A key-value attached to a [PyRecord], converted to the matching Python type on delivery.
+#[derive(Clone, Debug)]
+#[cfg_attr(not(any(feature = "kv", feature = "tracing")), allow(dead_code))]
+pub(crate) enum ExtraValue {
+    Bool(bool),
+    I64(i64),
+    U64(u64),
+    F64(f64),
+    Str(String),
+}
+
+impl<'py> IntoPyObject<'py> for &ExtraValue {
+    type Target = PyAny;
+    type Output = Bound<'py, PyAny>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> PyResult<Self::Output> {
+        Ok(match self {
+            ExtraValue::Bool(v) => v.into_pyobject(py)?.to_owned().into_any(),
+            ExtraValue::I64(v) => v.into_pyobject(py)?.into_any(),
+            ExtraValue::U64(v) => v.into_pyobject(py)?.into_any(),
+            ExtraValue::F64(v) => v.into_pyobject(py)?.into_any(),
+            ExtraValue::Str(v) => v.into_pyobject(py)?.into_any(),
+        })
+    }
+}
+
+impl PyRecord {
+    /// Converts a Rust target (`foo::bar`) into a Python logger name (`foo.bar`).
+    pub(crate) fn logger_name(target: &str) -> String {
+        target.replace("::", ".")
+    }
+}
+
+struct Queue {
+    state: Mutex<State>,
+    /// Signaled when records are buffered.
+    ready: Condvar,
+    /// Signaled when the worker is done delivering a batch of records.
+    idle: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    records: VecDeque<PyRecord>,
+    /// Whether the worker took records out of the buffer that it is still delivering.
+    delivering: bool,
+}
+
+impl State {
+    /// Buffers `record`, or discards it if the buffer is full.
+    fn push_back(&mut self, record: PyRecord) {
+        if self.records.len() < BUFFER_CAPACITY {
+            self.records.push_back(record);
+        } else {
+            OVERFLOWED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Queue {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+static QUEUE: OnceLock<Queue> = OnceLock::new();
+
+/// Returns the buffer, starting the worker thread that drains it on first use.
+fn queue() -> &'static Queue {
+    QUEUE.get_or_init(|| {
+        std::thread::Builder::new()
+            .name("pyo3-pylogger".to_string())
+            .spawn(worker)
+            .expect("failed to spawn the pyo3-pylogger dispatch thread");
+        Queue {
+            state: Mutex::new(State::default()),
+            ready: Condvar::new(),
+            idle: Condvar::new(),
+        }
+    })
+}
+
+/// Returns `true` if the calling thread is currently attached to an initialized interpreter.
+fn is_attached() -> bool {
+    // SAFETY: both functions may be called at any time, with or without the GIL held.
+    unsafe { pyo3::ffi::Py_IsInitialized() != 0 && pyo3::ffi::PyGILState_Check() != 0 }
+}
+
+/// Hands a record to Python, inline if this thread is attached to the interpreter and buffered otherwise.
+///
+/// Inline delivery first drains anything still buffered, so ordering is preserved on a best-effort basis.
+pub(crate) fn dispatch(record: PyRecord) {
+    if is_attached() {
+        let mut pending = take_pending();
+        pending.push_back(record);
+        Python::try_attach(|py| emit_all(py, pending));
+        return;
+    }
+
+    let queue = queue();
+    queue.lock().push_back(record);
+    queue.ready.notify_one();
+}
+
+/// Delivers all buffered records.
+///
+/// Threads attached to the interpreter deliver them inline. Other threads must not wait for the GIL, as the
+/// thread holding it may be waiting on them: they wait for the worker instead, for at most [FLUSH_TIMEOUT].
+#[cfg(feature = "log")]
+pub(crate) fn flush() {
+    if is_attached() {
+        let pending = take_pending();
+        if !pending.is_empty() {
+            Python::try_attach(|py| emit_all(py, pending));
+        }
+        return;
+    }
+
+    let Some(queue) = QUEUE.get() else {
+        return;
+    };
+    let state = queue.lock();
+    queue.ready.notify_one();
+    let _ = queue
+        .idle
+        .wait_timeout_while(state, FLUSH_TIMEOUT, |state| {
+            !state.records.is_empty() || state.delivering
+        });
+}
+
+fn take_pending() -> VecDeque<PyRecord> {
+    QUEUE
+        .get()
+        .map(|queue| std::mem::take(&mut queue.lock().records))
+        .unwrap_or_default()
+}
+
+fn worker() {
+    let queue = queue();
+    loop {
+        let mut pending = {
+            let mut state = queue.lock();
+            while state.records.is_empty() {
+                state = queue.ready.wait(state).unwrap_or_else(|e| e.into_inner());
+            }
+            state.delivering = true;
+            Some(std::mem::take(&mut state.records))
+        };
+
+        Python::try_attach(|py| emit_all(py, pending.take().unwrap_or_default()));
+
+        let mut state = queue.lock();
+        state.delivering = false;
+        // The interpreter isn't initialized yet (or is shutting down): put the records back and retry later.
+        if let Some(pending) = pending {
+            let mut records = std::mem::take(&mut state.records);
+            for record in pending.into_iter().chain(records.drain(..)) {
+                state.push_back(record);
+            }
+            drop(state);
+            queue.idle.notify_all();
+            std::thread::sleep(RETRY_INTERVAL);
+        } else {
+            drop(state);
+            queue.idle.notify_all();
+        }
+    }
+}
+
+fn emit_all(py: Python<'_>, records: VecDeque<PyRecord>) {
+    let _crossing = guard::Crossing::enter(guard::Direction::FromRust);
+    for record in records {
+        if let Err(e) = emit(py, &record) {
+            e.write_unraisable(py, None);
+        }
+    }
+}
+
+/// Builds a `logging.LogRecord` through the target logger's `makeRecord` and passes it to `Logger.handle`.
+fn emit(py: Python<'_>, record: &PyRecord) -> PyResult<()> {
+    let logging = py.import("logging")?;
+    let logger = logging.call_method1("getLogger", (&record.name,))?;
+    if !logger
+        .call_method1("isEnabledFor", (record.levelno,))?
+        .is_truthy()?
+    {
+        return Ok(());
+    }
+
+    let log_record = logger.call_method1(
+        "makeRecord",
+        (
+            &record.name,
+            record.levelno,
+            record.pathname.as_deref().unwrap_or_default(),
+            record.lineno.unwrap_or_default(),
+            &record.message,
+            PyTuple::empty(py),
+            py.None(),
+        ),
+    )?;
+    log_record.setattr(guard::ORIGIN_ATTR, true)?;
+
+    // `makeRecord` refuses `extra` keys that shadow record attributes; skip those instead of failing.
+    for (key, value) in &record.extra {
+        if !log_record.hasattr(key.as_str())? {
+            log_record.setattr(key.as_str(), value)?;
+        }
+    }
+
+    logger.call_method1("handle", (log_record,))?;
+    Ok(())
+}
@@ -0,0 +1,69 @@
+//! Loop protection between the Python -> Rust (`host_log`) and Rust -> Python (`dispatch`) paths.
+//!
+//! A record crossing the boundary in one direction must never cross it back. Two mechanisms detect this:
+//! - a thread-local marking that the current thread is already carrying a record across, which catches
+//!   handlers and loggers that synchronously call back into the other side;
+//! - an attribute set on every `LogRecord` created from a Rust record, which catches Python handlers that
+//!   pass records to another thread (e.g. `QueueHandler`) before they reach the `HostHandler`.
+//!
+//! Records caught by either are dropped and counted, see [dropped_records].
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use pyo3::prelude::*;
+
+/// Attribute set on `LogRecord`s created from Rust records.
+pub(crate) const ORIGIN_ATTR: &str = "_pyo3_pylogger_origin";
+
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the number of records dropped because they had already crossed between Python and Rust.
+///
+/// A steadily increasing count usually means a Rust logger forwarding into Python (such as
+/// [crate::PythonLogger]) and a `HostHandler` are installed on the same loggers.
+pub fn dropped_records() -> u64 {
+    DROPPED.load(Ordering::Relaxed)
+}
+
+pub(crate) fn record_dropped() {
+    DROPPED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// The direction a record is crossing the boundary in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Direction {
+    /// A Python `LogRecord` is being emitted as a Rust record by `host_log`.
+    FromPython,
+    /// A Rust record is being handed to Python's `logging`.
+    FromRust,
+}
+
+std::thread_local! {
+    static CROSSING: Cell<Option<Direction>> = const { Cell::new(None) };
+}
+
+/// Returns the direction of the record the current thread is carrying across, if any.
+pub(crate) fn crossing() -> Option<Direction> {
+    CROSSING.with(Cell::get)
+}
+
+/// Marks the current thread as carrying a record across until dropped.
+pub(crate) struct Crossing(Option<Direction>);
+
+impl Crossing {
+    pub(crate) fn enter(direction: Direction) -> Self {
+        Self(CROSSING.with(|c| c.replace(Some(direction))))
+    }
+}
+
+impl Drop for Crossing {
+    fn drop(&mut self) {
+        CROSSING.with(|c| c.set(self.0));
+    }
+}
+
+/// Returns `true` if `record` was created from a Rust record.
+pub(crate) fn is_from_rust(record: &Bound<'_, PyAny>) -> PyResult<bool> {
+    record.hasattr(ORIGIN_ATTR)
+}
@@ -5,10 +5,14 @@
 //! system and Rust's log crate.
 
 use pyo3::{
-    types::{PyAnyMethods, PyDict, PyDictMethods, PyListMethods},
+    types::{PyAnyMethods, PyDict, PyDictMethods, PyListMethods, PyTuple},
     Bound, PyAny, PyResult,
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+use crate::PyLoggerConfig;
+
+use crate::value::PyValue;
 
 /// A static hashset containing all standard [LogRecord](https://github.com/python/cpython/blob/8a00c9a4d2ce9d373b13f8f0a2265a65f4523293/Lib/logging/__init__.py#L286-L287) attributes defined in the CPython logging module.
 ///
@@ -39,14 +43,187 @@ pub static LOG_RECORD_KV_ATTRIBUTES: phf::Set<&'static str> = phf::phf_set! {
     "taskName",
 };
 
+/// Attributes that `logging.Formatter.format` sets on the records it formats, so that records reaching the
+/// `HostHandler` after another handler have them. `makeRecord` reserves these names like the standard ones.
+pub(crate) const FORMATTER_ATTRIBUTES: [&str; 2] = ["message", "asctime"];
+
+/// How the custom attributes of a [LogRecord](https://github.com/python/cpython/blob/8a00c9a4d2ce9d373b13f8f0a2265a65f4523293/Lib/logging/__init__.py#L286-L287)
+/// are told apart from the standard ones, set with [crate::PyLoggerConfig::with_kv_strategy].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum KvStrategy {
+    /// Scans `__dict__` from its end and stops at the first standard attribute.
+    ///
+    /// This relies on `makeRecord` adding `extra` after the standard attributes, and misses attributes added
+    /// before them, e.g. by a `LogRecordFactory` that reorders them.
+    #[default]
+    Trailing,
+    /// Takes every attribute of `__dict__` that is not a standard attribute, wherever it is.
+    ///
+    /// Attributes added by a `logging.Filter` or `LoggerAdapter` after the record was created are included.
+    FullDiff,
+    /// Takes every attribute of `__dict__` that a record made by the root logger's `makeRecord` does not have.
+    ///
+    /// The baseline is computed at registration from the running interpreter, so attributes added by a custom
+    /// `LogRecordFactory` or a newer Python version are excluded.
+    Baseline,
+    /// Only takes the given attributes, when present.
+    Allowlist(Vec<String>),
+}
+
+/// Where key-values from `extra` are placed, set with [crate::PyLoggerConfig::with_kv_namespace].
+///
+/// Whatever the namespace, a key that is already used by the crate (e.g. `message` or `exception.type`) is
+/// renamed with an `extra.` prefix instead of shadowing it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum KvNamespace {
+    /// Every key is forwarded as is.
+    #[default]
+    Flat,
+    /// Every key is prefixed with the given string, e.g. `py.` turns `user` into `py.user`.
+    Prefix(String),
+    /// All key-values are forwarded as a single map under the given key.
+    Nested(String),
+}
+
+/// Prefix given to keys colliding with a name already used by the crate.
+const RENAME_PREFIX: &str = "extra.";
+
+/// Finds the key-values of a record with [find_kv_args] and converts them, redacted, sorted by key and placed
+/// according to the configured [KvNamespace].
+pub(crate) fn extract(
+    record: &Bound<'_, PyAny>,
+    config: &PyLoggerConfig,
+) -> PyResult<Vec<(String, PyValue)>> {
+    let Some(kv_args) = find_kv_args(record, config)? else {
+        return Ok(Vec::new());
+    };
+
+    let mut entries: Vec<(String, PyValue)> = kv_args
+        .iter()
+        .map(|(key, value)| (key.clone(), PyValue::from_py(value)))
+        .collect();
+    for (key, value) in &mut entries {
+        config.redaction().redact_value(key, value);
+    }
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let entries = match config.kv_namespace() {
+        KvNamespace::Flat => entries,
+        KvNamespace::Prefix(prefix) => entries
+            .into_iter()
+            .map(|(key, value)| (format!("{prefix}{key}"), value))
+            .collect(),
+        KvNamespace::Nested(name) => vec![(name.clone(), PyValue::Map(entries))],
+    };
+
+    // Renaming may collide with another key, which is then renamed in turn.
+    let mut taken: HashSet<String> = entries.iter().map(|(key, _)| key.clone()).collect();
+    let mut renamed = Vec::with_capacity(entries.len());
+    for (mut key, value) in entries {
+        if crate::record::FIELDS.contains(&key.as_str()) {
+            taken.remove(&key);
+            while crate::record::FIELDS.contains(&key.as_str()) || taken.contains(&key) {
+                key = format!("{RENAME_PREFIX}{key}");
+            }
+            taken.insert(key.clone());
+        }
+        renamed.push((key, value));
+    }
+    renamed.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    Ok(renamed)
+}
+
+/// Which keys are forwarded among those found by [find_kv_args], see [crate::PyLoggerConfig::with_kv_exclude]
+/// and [crate::PyLoggerConfig::with_kv_include].
+#[derive(Clone, Debug, Default)]
+pub(crate) struct KvFilter {
+    pub exclude: Vec<String>,
+    pub include: Option<Vec<String>>,
+}
+
+impl KvFilter {
+    pub(crate) fn allows(&self, key: &str) -> bool {
+        let included = match &self.include {
+            Some(include) => include.iter().any(|pattern| glob_match(pattern, key)),
+            None => true,
+        };
+        included && !self.exclude.iter().any(|pattern| glob_match(pattern, key))
+    }
+}
+
+/// Matches `key` against a pattern in which `*` stands for any sequence of characters, e.g. `_*`.
+pub(crate) fn glob_match(pattern: &str, key: &str) -> bool {
+    let Some((first, rest)) = pattern.split_once('*') else {
+        return pattern == key;
+    };
+    let Some(mut remaining) = key.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = rest.split('*').collect();
+    let last = parts.pop().unwrap_or_default();
+    // Each part between two `*` is matched at its first occurrence, leaving the most room for the next ones.
+    for part in parts {
+        match remaining.find(part) {
+            Some(index) => remaining = &remaining[index + part.len()..],
+            None => return false,
+        }
+    }
+    remaining.ends_with(last)
+}
+
+/// Returns the attribute names of a blank `logging.LogRecord`, as defined by the running interpreter, and the
+/// [FORMATTER_ATTRIBUTES].
+///
+/// Unlike [baseline_attributes], this does not go through `makeRecord` and the `LogRecordFactory`, so only the
+/// attributes that the Python version itself defines when creating a record are returned. They complement
+/// [LOG_RECORD_KV_ATTRIBUTES] for Python versions newer than this crate. Attributes that Python only sets
+/// later, like those of a `Formatter`, are not detected and must be known to this crate.
+pub(crate) fn standard_attributes(logging: &Bound<'_, PyAny>) -> PyResult<HashSet<String>> {
+    let py = logging.py();
+    let record =
+        logging
+            .getattr("LogRecord")?
+            .call1(("", 0, "", 0, "", PyTuple::empty(py), py.None()))?;
+    let mut attributes = attribute_names(&record)?;
+    attributes.extend(FORMATTER_ATTRIBUTES.map(String::from));
+    Ok(attributes)
+}
+
+/// Returns the attribute names of a blank record made by the root logger, for [KvStrategy::Baseline].
+pub(crate) fn baseline_attributes(logging: &Bound<'_, PyAny>) -> PyResult<HashSet<String>> {
+    let py = logging.py();
+    let record = logging.call_method0("getLogger")?.call_method1(
+        "makeRecord",
+        ("", 0, "", 0, "", PyTuple::empty(py), py.None()),
+    )?;
+    let mut attributes = attribute_names(&record)?;
+    attributes.extend(FORMATTER_ATTRIBUTES.map(String::from));
+    Ok(attributes)
+}
+
+fn attribute_names(record: &Bound<'_, PyAny>) -> PyResult<HashSet<String>> {
+    let dict: Bound<'_, PyDict> = record.getattr("__dict__")?.extract()?;
+    Ok(dict.keys().iter().map(|key| key.to_string()).collect())
+}
+
+/// Whether `key` is a standard `LogRecord` attribute, either known to this crate or detected at registration,
+/// or one of the [FORMATTER_ATTRIBUTES].
+fn is_standard(config: &PyLoggerConfig, key: &str) -> bool {
+    LOG_RECORD_KV_ATTRIBUTES.contains(key)
+        || FORMATTER_ATTRIBUTES.contains(&key)
+        || config.kv_standard_attributes().contains(key)
+}
+
 /// Extracts custom key-value pairs from a Python LogRecord object.
 ///
 /// This function examines the `__dict__` of a LogRecord(https://github.com/python/cpython/blob/8a00c9a4d2ce9d373b13f8f0a2265a65f4523293/Lib/logging/__init__.py#L286-L287) object and identifies any attributes
-/// that are not part of the standard [LogRecord](https://github.com/python/cpython/blob/8a00c9a4d2ce9d373b13f8f0a2265a65f4523293/Lib/logging/__init__.py#L286-L287) attributes. These custom attributes are
-/// treated as key-value pairs for structured logging.
+/// that are not part of the standard [LogRecord](https://github.com/python/cpython/blob/8a00c9a4d2ce9d373b13f8f0a2265a65f4523293/Lib/logging/__init__.py#L286-L287) attributes, according to the
+/// configured [KvStrategy]. These custom attributes are treated as key-value pairs for structured logging.
 ///
 /// # Arguments
 /// * `record` - A reference to a Python LogRecord object
+/// * `config` - The configuration the record is handled with
 ///
 /// # Returns
 /// * `PyResult<Option<HashMap<String, pyo3::Bound<'a, pyo3::PyAny>>>>` - If custom attributes
@@ -54,50 +231,79 @@ pub static LOG_RECORD_KV_ATTRIBUTES: phf::Set<&'static str> = phf::phf_set! {
 ///   attributes are present.
 ///
 /// # Note
-/// This function relies on the fact that Python will not implement new attributes on the LogRecord object.
-/// If new attributes are added, this function will not be able to filter them out and will return them as key-value pairs.
-/// In that future, [LOG_RECORD_KV_ATTRIBUTES] will need to be updated to include the new attributes.
-/// This is an unfortunate side effect of using the `__dict__` attribute to extract key-value pairs. However, there are no other ways to handle this given that CPython does not distinguish between user-provided attributes and attributes created by the logging module.
+/// CPython does not distinguish between user-provided attributes and attributes created by the logging module,
+/// so standard attributes are recognized by name: those in [LOG_RECORD_KV_ATTRIBUTES], and those of a blank
+/// `LogRecord` of the running interpreter, detected at registration. Attributes that Python only adds to some
+/// records, and that neither of those contain, are returned as key-value pairs.
 pub fn find_kv_args<'a>(
     record: &Bound<'a, PyAny>,
+    config: &PyLoggerConfig,
 ) -> PyResult<Option<std::collections::HashMap<String, pyo3::Bound<'a, pyo3::PyAny>>>> {
     let dict: Bound<'_, PyDict> = record.getattr("__dict__")?.extract()?;
 
-    // We can abuse the fact that Python dictionaries are ordered by insertion order to reverse iterate over the keys
-    // and stop at the first key that is not a predefined key-value pair attribute.
-    let mut kv_args: Option<HashMap<String, pyo3::Bound<'_, pyo3::PyAny>>> = None;
+    let mut kv_args: HashMap<String, pyo3::Bound<'_, pyo3::PyAny>> = HashMap::new();
 
-    for item in dict.items().iter().rev() {
-        let (key, value) =
-            item.extract::<(pyo3::Bound<'_, pyo3::PyAny>, pyo3::Bound<'_, pyo3::PyAny>)>()?;
+    match config.kv_strategy() {
+        KvStrategy::Trailing => {
+            // We can abuse the fact that Python dictionaries are ordered by insertion order to reverse iterate over the keys
+            // and stop at the first key that is not a predefined key-value pair attribute.
+            for item in dict.items().iter().rev() {
+                let (key, value) =
+                    item.extract::<(pyo3::Bound<'_, pyo3::PyAny>, pyo3::Bound<'_, pyo3::PyAny>)>()?;
 
-        let key_str = key.to_string();
-        if LOG_RECORD_KV_ATTRIBUTES.contains(&key_str) {
-            break;
+                let key_str = key.to_string();
+                // Set by a `Formatter` after `extra`, so found before it.
+                if FORMATTER_ATTRIBUTES.contains(&key_str.as_str()) {
+                    continue;
+                }
+                if is_standard(config, &key_str) {
+                    break;
+                }
+                kv_args.insert(key_str, value);
+            }
         }
-        if kv_args.is_none() {
-            kv_args = Some(HashMap::new());
+        KvStrategy::FullDiff => {
+            for (key, value) in dict.iter() {
+                let key_str = key.to_string();
+                if !is_standard(config, &key_str) {
+                    kv_args.insert(key_str, value);
+                }
+            }
+        }
+        KvStrategy::Baseline => {
+            let baseline = config.kv_baseline();
+            for (key, value) in dict.iter() {
+                let key_str = key.to_string();
+                if !baseline.contains(&key_str) {
+                    kv_args.insert(key_str, value);
+                }
+            }
+        }
+        KvStrategy::Allowlist(keys) => {
+            for key in keys {
+                if let Some(value) = dict.get_item(key)? {
+                    kv_args.insert(key.clone(), value);
+                }
+            }
         }
-
-        kv_args.as_mut().unwrap().insert(key_str, value);
     }
 
-    Ok(kv_args)
+    kv_args.retain(|key, _| config.kv_filter().allows(key));
+
+    Ok((!kv_args.is_empty()).then_some(kv_args))
 }
 
 /// A wrapper struct that implements the `log::kv::Source` trait for Python key-value pairs.
 ///
 /// This struct allows Python LogRecord custom attributes to be used with Rust's
 /// structured logging system by implementing the necessary trait for key-value handling.
-///
-/// # Type Parameters
-/// * `'a` - The lifetime of the contained Python values
-#[cfg(feature = "log")]
-pub struct KVSource<'a>(pub HashMap<String, pyo3::Bound<'a, pyo3::PyAny>>);
-
-#[cfg(feature = "log")]
-impl log::kv::Source for KVSource<'_> {
-    /// Visits each key-value pair in the source, converting Python values to debug representations.
+/// The values are converted to [PyValue]s up front by [extract], so they are forwarded with their Rust types.
+#[cfg(feature = "kv")]
+pub struct KVSource(pub Vec<(String, PyValue)>);
+
+#[cfg(feature = "kv")]
+impl log::kv::Source for KVSource {
+    /// Visits each key-value pair in the source, see [PyValue::as_log] for how values are represented.
     ///
     /// # Arguments
     /// * `visitor` - The visitor that will process each key-value pair
@@ -110,10 +316,94 @@ impl log::kv::Source for KVSource<'_> {
         visitor: &mut dyn log::kv::VisitSource<'kvs>,
     ) -> Result<(), log::kv::Error> {
         for (key, value) in &self.0 {
-            let v: log::kv::Value<'_> = log::kv::Value::from_debug(value);
-
-            visitor.visit_pair(log::kv::Key::from_str(key), v)?;
+            visitor.visit_pair(log::kv::Key::from_str(key), value.as_log())?;
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_without_wildcard() {
+        assert!(glob_match("user", "user"));
+        assert!(!glob_match("user", "users"));
+        assert!(!glob_match("user", "use"));
+        assert!(glob_match("", ""));
+        assert!(!glob_match("", "user"));
+    }
+
+    #[test]
+    fn glob_wildcard_positions() {
+        // Start
+        assert!(glob_match("*_id", "user_id"));
+        assert!(glob_match("*_id", "_id"));
+        assert!(!glob_match("*_id", "user_ids"));
+        // Middle
+        assert!(glob_match("db_*_url", "db_replica_url"));
+        assert!(glob_match("db_*_url", "db__url"));
+        assert!(!glob_match("db_*_url", "db_url"));
+        // End
+        assert!(glob_match("_*", "_private"));
+        assert!(glob_match("_*", "_"));
+        assert!(!glob_match("_*", "public_"));
+        // Alone
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*", "anything"));
+    }
+
+    #[test]
+    fn glob_repeated_wildcards() {
+        assert!(glob_match("*password*", "password"));
+        assert!(glob_match("*password*", "db_password_hash"));
+        assert!(!glob_match("*password*", "passwd"));
+        assert!(glob_match("a*b*c", "abc"));
+        assert!(glob_match("a*b*c", "a_b_b_c"));
+        assert!(!glob_match("a*b*c", "acb"));
+        assert!(glob_match("**", "x"));
+        // The prefix, middle parts and suffix must not overlap.
+        assert!(!glob_match("ab*b", "ab"));
+        assert!(!glob_match("a*a", "a"));
+        assert!(glob_match("a*a", "aa"));
+        assert!(!glob_match("*ab*ab", "xab"));
+    }
+
+    fn filter(exclude: &[&str], include: Option<&[&str]>) -> KvFilter {
+        let patterns = |patterns: &[&str]| patterns.iter().map(|p| p.to_string()).collect();
+        KvFilter {
+            exclude: patterns(exclude),
+            include: include.map(patterns),
+        }
+    }
+
+    #[test]
+    fn filter_default_allows_everything() {
+        let filter = filter(&[], None);
+        assert!(filter.allows("user"));
+        assert!(filter.allows("_private"));
+    }
+
+    #[test]
+    fn filter_exclude() {
+        let filter = filter(&["request", "_*"], None);
+        assert!(filter.allows("user"));
+        assert!(!filter.allows("request"));
+        assert!(!filter.allows("_private"));
+    }
+
+    #[test]
+    fn filter_include_and_exclude() {
+        let filter = filter(&["*_secret"], Some(&["user*", "order_id"]));
+        assert!(filter.allows("user"));
+        assert!(filter.allows("user_name"));
+        assert!(filter.allows("order_id"));
+        assert!(!filter.allows("amount"));
+        // Exclusion wins over inclusion.
+        assert!(!filter.allows("user_secret"));
+
+        // An empty include list allows nothing.
+        assert!(!self::filter(&[], Some(&[])).allows("user"));
+    }
+}
@@ -0,0 +1,162 @@
+//! A `tracing_subscriber::Layer` that forwards Rust `tracing` events into Python's `logging` module.
+//!
+//! This is the reverse direction of the `tracing` backend of the `HostHandler`.
+
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::dispatch::{self, ExtraValue, PyRecord};
+use crate::guard;
+use crate::PyLevel;
+
+/// Forwards `tracing` events to `logging.getLogger(target).handle()`.
+///
+/// The event's target becomes the Python logger name (`foo::bar` -> `foo.bar`), and its level, file and line
+/// populate `levelno`, `pathname` and `lineno` of the `LogRecord`. Event fields, and the fields of the spans
+/// the event is in, are set as record attributes the same way Python's `extra` does, so they round-trip
+/// as key-values. The names of the enclosing spans are set as `spans`, from the root, separated by `:`.
+///
+/// Events that Python logged through the `HostHandler` are never sent back to Python: they are dropped and
+/// counted by [crate::dropped_records].
+///
+/// # Example
+/// ```no_run
+/// use tracing_subscriber::prelude::*;
+///
+/// tracing_subscriber::registry()
+///     .with(pyo3_pylogger::PythonLayer::new())
+///     .init();
+/// ```
+#[derive(Debug, Default)]
+pub struct PythonLayer {
+    _private: (),
+}
+
+impl PythonLayer {
+    /// Creates a layer forwarding every event it is given. Use `Layer::with_filter` to restrict it.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// The fields recorded on a span, stored in its extensions.
+struct SpanFields(Vec<(String, ExtraValue)>);
+
+/// Collects the fields of an event or span, splitting out the `message` field of events.
+#[derive(Default)]
+struct FieldVisitor {
+    message: Option<String>,
+    fields: Vec<(String, ExtraValue)>,
+}
+
+impl FieldVisitor {
+    fn insert(&mut self, field: &Field, value: ExtraValue) {
+        self.fields.retain(|(key, _)| key != field.name());
+        self.fields.push((field.name().to_string(), value));
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, ExtraValue::F64(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, ExtraValue::I64(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, ExtraValue::U64(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, ExtraValue::Bool(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = Some(value.to_string());
+        } else {
+            self.insert(field, ExtraValue::Str(value.to_string()));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message = Some(format!("{value:?}"));
+        } else {
+            self.insert(field, ExtraValue::Str(format!("{value:?}")));
+        }
+    }
+}
+
+impl<S> Layer<S> for PythonLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut visitor = FieldVisitor::default();
+        attrs.record(&mut visitor);
+        span.extensions_mut().insert(SpanFields(visitor.fields));
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(SpanFields(fields)) = extensions.get_mut::<SpanFields>() {
+            let mut visitor = FieldVisitor {
+                message: None,
+                fields: std::mem::take(fields),
+            };
+            values.record(&mut visitor);
+            *fields = visitor.fields;
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        // Events emitted by `host_log` came from Python in the first place.
+        if guard::crossing().is_some() {
+            guard::record_dropped();
+            return;
+        }
+
+        let mut visitor = FieldVisitor::default();
+        let mut spans = Vec::new();
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                spans.push(span.name());
+                if let Some(SpanFields(fields)) = span.extensions().get::<SpanFields>() {
+                    for (key, value) in fields {
+                        visitor.fields.retain(|(k, _)| k != key);
+                        visitor.fields.push((key.clone(), value.clone()));
+                    }
+                }
+            }
+        }
+        if !spans.is_empty() {
+            visitor
+                .fields
+                .push(("spans".to_string(), ExtraValue::Str(spans.join(":"))));
+        }
+        event.record(&mut visitor);
+
+        let metadata = event.metadata();
+        dispatch::dispatch(PyRecord {
+            name: PyRecord::logger_name(metadata.target()),
+            levelno: PyLevel::from(*metadata.level()).levelno(),
+            pathname: metadata.file().map(str::to_string),
+            lineno: metadata.line(),
+            message: visitor.message.unwrap_or_default(),
+            extra: visitor.fields,
+        });
+    }
+}
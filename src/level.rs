@@ -1,53 +1,296 @@
-/// A wrapper type for logging levels that supports both `tracing` and `log` features.
-#[cfg(feature = "tracing")]
-pub(crate) struct Level(pub tracing::Level);
+use std::sync::Arc;
+
+/// The level of a Python record, as understood by the Rust side.
+///
+/// Python records are mapped to one of these levels, by default with [get_level] or by the table or function
+/// registered with [crate::PyLoggerConfig::with_level_table] and [crate::PyLoggerConfig::with_level_mapper].
+/// The level is then converted to the level of the active backend with the `From` implementations:
+/// `Critical` and `Error` both become `Error`, and `Warning` becomes `Warn`.
+///
+/// Converting from a Rust level, or to a number with [PyLevel::levelno], gives the matching Python level
+/// for records going from Rust to Python.
+///
+/// ```
+/// use pyo3_pylogger::PyLevel;
+///
+/// assert_eq!(PyLevel::try_from(25), Ok(PyLevel::Info));
+/// assert_eq!(PyLevel::Critical.levelno(), 50);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PyLevel {
+    Trace,
+    Debug,
+    Info,
+    Warning,
+    Error,
+    Critical,
+}
+
+impl PyLevel {
+    /// The Python level number of this level, e.g. `50` for `Critical`.
+    ///
+    /// Python has no TRACE level; `Trace` is `5`, below `logging.DEBUG`.
+    pub fn levelno(self) -> i32 {
+        match self {
+            PyLevel::Critical => 50,
+            PyLevel::Error => 40,
+            PyLevel::Warning => 30,
+            PyLevel::Info => 20,
+            PyLevel::Debug => 10,
+            PyLevel::Trace => 5,
+        }
+    }
+}
+
+/// The error returned when converting a negative number to a [PyLevel].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TryFromLevelError(i32);
+
+impl std::fmt::Display for TryFromLevelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} is not a valid Python level number", self.0)
+    }
+}
+
+impl std::error::Error for TryFromLevelError {}
+
+impl TryFrom<i32> for PyLevel {
+    type Error = TryFromLevelError;
+
+    /// Maps a Python level number with the default thresholds, see [PyLevel].
+    fn try_from(levelno: i32) -> Result<Self, TryFromLevelError> {
+        if levelno < 0 {
+            return Err(TryFromLevelError(levelno));
+        }
+        Ok(get_level(levelno))
+    }
+}
+
+impl From<PyLevel> for i32 {
+    fn from(level: PyLevel) -> Self {
+        level.levelno()
+    }
+}
 
-/// A wrapper type for logging levels that supports both `tracing` and `log` features.
 #[cfg(feature = "log")]
-pub(crate) struct Level(pub log::Level);
+impl From<PyLevel> for log::Level {
+    fn from(level: PyLevel) -> Self {
+        match level {
+            PyLevel::Critical | PyLevel::Error => log::Level::Error,
+            PyLevel::Warning => log::Level::Warn,
+            PyLevel::Info => log::Level::Info,
+            PyLevel::Debug => log::Level::Debug,
+            PyLevel::Trace => log::Level::Trace,
+        }
+    }
+}
+
+#[cfg(feature = "log")]
+impl From<log::Level> for PyLevel {
+    fn from(level: log::Level) -> Self {
+        match level {
+            log::Level::Error => PyLevel::Error,
+            log::Level::Warn => PyLevel::Warning,
+            log::Level::Info => PyLevel::Info,
+            log::Level::Debug => PyLevel::Debug,
+            log::Level::Trace => PyLevel::Trace,
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl From<PyLevel> for tracing::Level {
+    fn from(level: PyLevel) -> Self {
+        match level {
+            PyLevel::Critical | PyLevel::Error => tracing::Level::ERROR,
+            PyLevel::Warning => tracing::Level::WARN,
+            PyLevel::Info => tracing::Level::INFO,
+            PyLevel::Debug => tracing::Level::DEBUG,
+            PyLevel::Trace => tracing::Level::TRACE,
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl From<tracing::Level> for PyLevel {
+    fn from(level: tracing::Level) -> Self {
+        match level {
+            tracing::Level::ERROR => PyLevel::Error,
+            tracing::Level::WARN => PyLevel::Warning,
+            tracing::Level::INFO => PyLevel::Info,
+            tracing::Level::DEBUG => PyLevel::Debug,
+            tracing::Level::TRACE => PyLevel::Trace,
+        }
+    }
+}
 
 /// Converts a numeric level value to the appropriate logging Level.
 ///
 /// # Arguments
 ///
-/// * `level` - A u8 value representing the logging level:
-///   * 40+ = Error
-///   * 30-39 = Warn
+/// * `level` - A Python level number:
+///   * 50+ = Critical
+///   * 40-49 = Error
+///   * 30-39 = Warning
 ///   * 20-29 = Info
 ///   * 10-19 = Debug
-///   * 0-9 = Trace
-///
-/// # Returns
-///
-/// Returns a `Level` wrapper containing either a `tracing::Level` or `log::Level`
-/// depending on which feature is enabled.
-pub(crate) fn get_level(level: u8) -> Level {
-    #[cfg(feature = "log")]
-    {
-        if level.ge(&40u8) {
-            Level(log::Level::Error)
-        } else if level.ge(&30u8) {
-            Level(log::Level::Warn)
-        } else if level.ge(&20u8) {
-            Level(log::Level::Info)
-        } else if level.ge(&10u8) {
-            Level(log::Level::Debug)
-        } else {
-            Level(log::Level::Trace)
-        }
-    }
-    #[cfg(feature = "tracing")]
-    {
-        if level.ge(&40u8) {
-            Level(tracing::Level::ERROR)
-        } else if level.ge(&30u8) {
-            Level(tracing::Level::WARN)
-        } else if level.ge(&20u8) {
-            Level(tracing::Level::INFO)
-        } else if level.ge(&10u8) {
-            Level(tracing::Level::DEBUG)
-        } else {
-            Level(tracing::Level::TRACE)
+///   * below 10 = Trace
+pub(crate) fn get_level(level: i32) -> PyLevel {
+    if level >= 50 {
+        PyLevel::Critical
+    } else if level >= 40 {
+        PyLevel::Error
+    } else if level >= 30 {
+        PyLevel::Warning
+    } else if level >= 20 {
+        PyLevel::Info
+    } else if level >= 10 {
+        PyLevel::Debug
+    } else {
+        PyLevel::Trace
+    }
+}
+
+/// How Python level numbers are mapped to a [PyLevel].
+#[derive(Clone, Default)]
+pub(crate) enum LevelMapping {
+    /// [get_level].
+    #[default]
+    Default,
+    /// `(threshold, level)` pairs sorted by descending threshold. A level number maps to the level of the
+    /// first threshold it reaches, or to `Trace` if it is below all of them.
+    Table(Vec<(i32, PyLevel)>),
+    Custom(Arc<dyn Fn(i32) -> PyLevel + Send + Sync>),
+}
+
+impl LevelMapping {
+    pub(crate) fn table(table: impl IntoIterator<Item = (i32, PyLevel)>) -> Self {
+        let mut table: Vec<_> = table.into_iter().collect();
+        table.sort_by_key(|(threshold, _)| std::cmp::Reverse(*threshold));
+        LevelMapping::Table(table)
+    }
+
+    /// The lowest Python level number mapped to `level` or above, or `None` if it can't be determined.
+    pub(crate) fn threshold(&self, level: PyLevel) -> Option<i32> {
+        if level == PyLevel::Trace {
+            return Some(0);
+        }
+        match self {
+            LevelMapping::Default => Some(level.levelno()),
+            LevelMapping::Table(table) => table
+                .iter()
+                .filter(|(_, mapped)| *mapped >= level)
+                .map(|(threshold, _)| *threshold)
+                .min(),
+            LevelMapping::Custom(_) => None,
+        }
+    }
+
+    pub(crate) fn map(&self, levelno: i32) -> PyLevel {
+        match self {
+            LevelMapping::Default => get_level(levelno),
+            LevelMapping::Table(table) => table
+                .iter()
+                .find(|(threshold, _)| levelno >= *threshold)
+                .map_or(PyLevel::Trace, |(_, level)| *level),
+            LevelMapping::Custom(f) => f(levelno),
+        }
+    }
+}
+
+impl std::fmt::Debug for LevelMapping {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LevelMapping::Default => f.write_str("Default"),
+            LevelMapping::Table(table) => f.debug_tuple("Table").field(table).finish(),
+            LevelMapping::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+/// Reads a Python level number, saturating integers outside of the `i64` range.
+pub(crate) fn levelno(value: &pyo3::Bound<'_, pyo3::PyAny>) -> pyo3::PyResult<i64> {
+    use pyo3::types::{PyAnyMethods, PyInt};
+
+    match value.extract::<i64>() {
+        Ok(levelno) => Ok(levelno),
+        Err(_) if value.is_instance_of::<PyInt>() => {
+            Ok(if value.gt(0)? { i64::MAX } else { i64::MIN })
         }
+        Err(e) => Err(e),
+    }
+}
+
+/// Saturates a Python level number to the `i32` range of level mappings.
+pub(crate) fn saturate(levelno: i64) -> i32 {
+    levelno.clamp(i32::MIN.into(), i32::MAX.into()) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_mapping() {
+        let mapping = LevelMapping::Default;
+        assert_eq!(mapping.map(i32::MIN), PyLevel::Trace);
+        assert_eq!(mapping.map(9), PyLevel::Trace);
+        assert_eq!(mapping.map(10), PyLevel::Debug);
+        assert_eq!(mapping.map(25), PyLevel::Info);
+        assert_eq!(mapping.map(45), PyLevel::Error);
+        assert_eq!(mapping.map(i32::MAX), PyLevel::Critical);
+        assert_eq!(mapping.threshold(PyLevel::Trace), Some(0));
+        assert_eq!(mapping.threshold(PyLevel::Warning), Some(30));
+    }
+
+    #[test]
+    fn table_mapping() {
+        // Unsorted on purpose, with two thresholds mapped to `Warning`.
+        let mapping = LevelMapping::table([
+            (20, PyLevel::Info),
+            (45, PyLevel::Error),
+            (25, PyLevel::Warning),
+            (30, PyLevel::Warning),
+            (10, PyLevel::Debug),
+        ]);
+        assert_eq!(mapping.map(5), PyLevel::Trace);
+        assert_eq!(mapping.map(15), PyLevel::Debug);
+        assert_eq!(mapping.map(20), PyLevel::Info);
+        assert_eq!(mapping.map(25), PyLevel::Warning);
+        assert_eq!(mapping.map(40), PyLevel::Warning);
+        assert_eq!(mapping.map(50), PyLevel::Error);
+
+        assert_eq!(mapping.threshold(PyLevel::Trace), Some(0));
+        assert_eq!(mapping.threshold(PyLevel::Info), Some(20));
+        assert_eq!(mapping.threshold(PyLevel::Warning), Some(25));
+        assert_eq!(mapping.threshold(PyLevel::Error), Some(45));
+        // Nothing maps to `Critical`.
+        assert_eq!(mapping.threshold(PyLevel::Critical), None);
+    }
+
+    #[test]
+    fn custom_mapping() {
+        let mapping = LevelMapping::Custom(Arc::new(|levelno| {
+            if levelno % 2 == 0 {
+                PyLevel::Info
+            } else {
+                PyLevel::Error
+            }
+        }));
+        assert_eq!(mapping.map(20), PyLevel::Info);
+        assert_eq!(mapping.map(21), PyLevel::Error);
+        assert_eq!(mapping.threshold(PyLevel::Info), None);
+        assert_eq!(mapping.threshold(PyLevel::Trace), Some(0));
+    }
+
+    #[test]
+    fn saturates_to_i32() {
+        assert_eq!(saturate(1 << 40), i32::MAX);
+        assert_eq!(saturate(-(1 << 40)), i32::MIN);
+        assert_eq!(saturate(25), 25);
+        assert_eq!(
+            LevelMapping::Default.map(saturate(1 << 40)),
+            PyLevel::Critical
+        );
     }
 }
@@ -1,217 +1,318 @@
-use std::ffi::CString;
-
 use pyo3::prelude::*;
 
-#[cfg(all(feature = "tracing", feature = "log"))]
-compile_error!("The features 'tracing' and 'log' cannot be enabled at the same time. Please choose one logging implementation.");
-
-#[cfg(all(feature = "tracing", feature = "kv"))]
-compile_error!("The features 'tracing' and 'kv' cannot be enabled at the same time. Please tracing and tracing-kv features instead.");
-
-#[cfg(all(feature = "log", feature = "tracing-kv"))]
-compile_error!("The features 'log' and 'tracing-kv' cannot be enabled at the same time. Please log and kv features instead.");
+#[cfg(not(any(feature = "log", feature = "tracing")))]
+compile_error!("At least one of the features 'log' and 'tracing' must be enabled.");
 
 #[cfg(feature = "kv-common")]
 mod kv;
 
+#[cfg(feature = "tracing")]
+mod callsite;
+mod config;
+mod dispatch;
+mod guard;
+#[cfg(feature = "tracing")]
+mod layer;
 mod level;
+#[cfg(feature = "log")]
+mod logger;
+mod record;
+mod redact;
+mod sync;
+mod target;
+#[cfg(feature = "kv-common")]
+mod value;
+
+pub use config::{Backend, PyLoggerConfig};
+pub use dispatch::overflowed_records;
+pub use guard::dropped_records;
+#[cfg(feature = "kv-common")]
+pub use kv::{KvNamespace, KvStrategy};
+#[cfg(feature = "tracing")]
+pub use layer::PythonLayer;
+pub use level::{PyLevel, TryFromLevelError};
+#[cfg(feature = "log")]
+pub use logger::PythonLogger;
+pub use redact::RedactRule;
+pub use sync::sync_levels;
+pub use target::{TargetMode, TargetRule};
 
 /// Convenience function to register the rust logger with the Python logging instance.
+///
+/// Equivalent to `PyLoggerConfig::new(target).register()`.
 pub fn register(target: &str) {
-    Python::attach(|py| {
-        // Extend the `logging` module to interact with log
-        setup_logging(py, target)
-    })
-    .unwrap();
+    PyLoggerConfig::new(target).register();
 }
 
 /// Consume a Python `logging.LogRecord` and emit a Rust `Log` instead.
 #[pyfunction]
 fn host_log(record: Bound<'_, PyAny>, rust_target: &str) -> PyResult<()> {
-    let level = record.getattr("levelno")?.extract()?;
-    let message = record.getattr("getMessage")?.call0()?.to_string();
-    let pathname = record.getattr("pathname")?.extract::<String>()?;
-    let lineno = record.getattr("lineno")?.extract::<u32>()?;
+    // Either the record was forwarded from Rust, or a Rust logger called back into Python while emitting it.
+    if guard::crossing().is_some() || guard::is_from_rust(&record)? {
+        guard::record_dropped();
+        return Ok(());
+    }
+
+    let config = config::lookup(rust_target);
+    if config.level_sync() {
+        sync::sync(record.py(), &config)?;
+    }
+
+    let levelno = level::levelno(&record.getattr("levelno")?)?;
+    let level = config.level(levelno);
 
     let logger_name = record.getattr("name")?.extract::<String>()?;
 
-    let full_target: Option<String> = if logger_name.trim().is_empty() || logger_name == "root" {
-        None
-    } else {
-        // Libraries (ex: tracing_subscriber::filter::Directive) expect rust-style targets like foo::bar,
-        // and may not deal well with "." as a module separator:
-        let logger_name = logger_name.replace('.', "::");
-        Some(format!("{rust_target}::{logger_name}"))
-    };
-    let target = full_target.as_deref().unwrap_or(rust_target);
+    let target = target::target(
+        config.target_mode(),
+        config.target_rules(),
+        rust_target,
+        &logger_name,
+    );
+    let target = target.as_ref();
+    let location = record::SourceLocation::from_record(&record, &logger_name)?;
+
+    // Skip formatting the message and extracting key-values for records the Rust side discards anyway.
+    if !enabled(&config, target, level, &location) {
+        return Ok(());
+    }
+
+    let message = record.getattr("getMessage")?.call0()?.to_string();
+    let message = config.redaction().redact_text(&message);
+    let context = record::RecordContext::from_record(&record, &config, location, levelno)?;
+    let _crossing = guard::Crossing::enter(guard::Direction::FromPython);
+    handle_record(record, &config, &context, target, &message, level)
+}
 
-    handle_record(record, target, &message, lineno, &pathname, level)?;
+/// Whether the configured backend would keep a record with this target, level and location.
+fn enabled(
+    config: &PyLoggerConfig,
+    target: &str,
+    level: PyLevel,
+    #[allow(unused_variables)] location: &record::SourceLocation,
+) -> bool {
+    match config.backend() {
+        #[cfg(feature = "log")]
+        Backend::Log => log_enabled(target, level),
+        #[cfg(feature = "tracing")]
+        Backend::Tracing => tracing_enabled(config, target, level, location),
+        #[cfg(all(feature = "log", feature = "tracing"))]
+        Backend::Both => {
+            log_enabled(target, level) || tracing_enabled(config, target, level, location)
+        }
+    }
+}
 
-    Ok(())
+/// Whether the `log` logger would keep a record with this target and level.
+#[cfg(feature = "log")]
+fn log_enabled(target: &str, level: PyLevel) -> bool {
+    let level = log::Level::from(level);
+    level <= log::max_level()
+        && log::logger().enabled(&log::Metadata::builder().level(level).target(target).build())
+}
+
+/// Whether the current `tracing` subscriber may keep a record with this target, level and location.
+#[cfg(feature = "tracing")]
+fn tracing_enabled(
+    #[allow(unused_variables)] config: &PyLoggerConfig,
+    target: &str,
+    level: PyLevel,
+    location: &record::SourceLocation,
+) -> bool {
+    let level = tracing::Level::from(level);
+    if !tracing::level_enabled!(level) {
+        return false;
+    }
+    // Filters on fields (e.g. `EnvFilter`'s `target[{user}]=debug`) can only be evaluated with the `extra`
+    // keys, which are only known once extracted: `emit_tracing` decides on the callsite that has them.
+    #[cfg(feature = "tracing-kv")]
+    if config.kv() {
+        return true;
+    }
+    callsite::callsite(&tracing_location(target, level, location), record::FIELDS).is_enabled()
+}
+
+#[cfg(feature = "tracing")]
+fn tracing_location<'a>(
+    target: &'a str,
+    level: tracing::Level,
+    location: &'a record::SourceLocation,
+) -> callsite::Location<'a> {
+    callsite::Location {
+        target,
+        file: &location.pathname,
+        line: location.lineno,
+        module_path: &location.module_path,
+        level,
+    }
 }
 
+/// The key-values of a record, see [kv::extract].
+#[cfg(feature = "kv-common")]
+type KvArgs = Vec<(String, value::PyValue)>;
+/// Without `kv-common`, records never have key-values.
+#[cfg(not(feature = "kv-common"))]
+type KvArgs = Vec<(String, std::convert::Infallible)>;
+
 fn handle_record(
     #[allow(unused_variables)] record: Bound<'_, PyAny>,
+    config: &PyLoggerConfig,
+    context: &record::RecordContext,
     target: &str,
     message: &str,
-    lineno: u32,
-    pathname: &str,
-    level: u8,
+    level: PyLevel,
 ) -> PyResult<()> {
-    // If log feature is enabled, use log::logger
-    let level = crate::level::get_level(level).0;
-
-    #[cfg(feature = "log")]
-    {
-        let mut metadata_builder = log::MetadataBuilder::new();
-        metadata_builder.target(target);
-
-        metadata_builder.level(level);
-
-        let mut record_builder = log::Record::builder();
-
-        #[cfg(feature = "kv")]
-        {
-            let kv_args = kv::find_kv_args(&record)?;
-
-            let kv_source = kv_args.map(kv::KVSource);
-            if let Some(kv_source) = kv_source {
-                log::logger().log(
-                    &record_builder
-                        .metadata(metadata_builder.build())
-                        .args(format_args!("{}", &message))
-                        .line(Some(lineno))
-                        .file(Some(pathname))
-                        .module_path(Some(pathname))
-                        .key_values(&kv_source)
-                        .build(),
-                );
-                return Ok(());
+    #[cfg(feature = "kv-common")]
+    let kv_args = if config.kv() {
+        kv::extract(&record, config)?
+    } else {
+        KvArgs::new()
+    };
+    #[cfg(not(feature = "kv-common"))]
+    let kv_args = KvArgs::new();
+
+    match config.backend() {
+        #[cfg(feature = "log")]
+        Backend::Log => emit_log(context, target, message, level, kv_args),
+        #[cfg(feature = "tracing")]
+        Backend::Tracing => emit_tracing(context, target, message, level, kv_args),
+        #[cfg(all(feature = "log", feature = "tracing"))]
+        Backend::Both => {
+            // Only one of the backends may want the record; `emit_tracing` checks its own side.
+            if log_enabled(target, level) {
+                emit_log(context, target, message, level, kv_args.clone());
             }
+            emit_tracing(context, target, message, level, kv_args);
         }
-
-        log::logger().log(
-            &record_builder
-                .metadata(metadata_builder.build())
-                .args(format_args!("{}", &message))
-                .line(Some(lineno))
-                .file(Some(pathname))
-                .module_path(Some(pathname))
-                .build(),
-        );
     }
+    Ok(())
+}
 
-    #[cfg(feature = "tracing")]
-    {
-        #[cfg(feature = "kv-common")]
-        {
-            let kv_args = kv::find_kv_args(&record)?;
-
-            let fields: std::collections::HashMap<String, Bound<'_, PyAny>> =
-                kv_args.unwrap_or_default();
-
-            // this is the only way to pass fields to tracing, unfortunately
-            // it's not possible as of mar 30 2025 to pass dynamic fields to tracing
-            // see: https://github.com/tokio-rs/tracing/issues/372
-            // Convert Python dictionary to a Rust value that can be serialized to JSON
-            let mut json_map = serde_json::Map::new();
-
-            // Convert each Python object in the HashMap to a JSON value
-            for (key, value) in fields.into_iter() {
-                let fallback = format!("{:?}", &value);
-                match pythonize::depythonize::<serde_json::Value>(&value) {
-                    Ok(json_value) => {
-                        json_map.insert(key.clone(), json_value);
-                    }
-                    Err(e) => {
-                        tracing::error!("Error converting Python object to JSON when parsing key={} with message='{}' : {:?}", e, key.clone(), message);
-                        // Handle conversion errors (optional)
-                        // Supported types: https://github.com/Jij-Inc/serde-pyobject/blob/32c3ac77c2ed09b654f7fbc960c5f273fd1bb85c/src/de.rs#L305
-                        json_map.insert(
-                            key.clone(),
-                            serde_json::Value::String(format!("{:?}", fallback)),
-                        );
-                    }
-                }
-            }
+#[cfg(feature = "log")]
+fn emit_log(
+    context: &record::RecordContext,
+    target: &str,
+    message: &str,
+    level: PyLevel,
+    #[allow(unused_variables)] kv_args: KvArgs,
+) {
+    let location = &context.location;
+
+    let mut metadata_builder = log::MetadataBuilder::new();
+    metadata_builder.target(target);
+
+    metadata_builder.level(level.into());
+
+    let mut record_builder = log::Record::builder();
+
+    #[cfg(feature = "kv")]
+    let kv_source = kv::KVSource(kv_args);
+    #[cfg(feature = "kv")]
+    let sources: [&dyn log::kv::Source; 2] = [&kv_source, context];
+    #[cfg(feature = "kv")]
+    record_builder.key_values(&sources);
+
+    // Without key-values, the context can only be forwarded as part of the message.
+    #[cfg(not(feature = "kv"))]
+    let message = context.append_to(message);
+
+    log::logger().log(
+        &record_builder
+            .metadata(metadata_builder.build())
+            .args(format_args!("{}", &message))
+            .line(Some(location.lineno))
+            .file(Some(location.pathname.as_str()))
+            .module_path(Some(location.module_path.as_str()))
+            .build(),
+    );
+}
 
-            // Create a JSON object from our map and convert to string
-            let json_value = serde_json::Value::Object(json_map);
-            let fields = serde_json::to_string(&json_value).unwrap_or_default();
-
-            match level {
-                tracing::Level::ERROR => {
-                    tracing::error!(%target, %pathname, %lineno, python_fields = %fields, "{}", message )
-                }
-                tracing::Level::WARN => {
-                    tracing::warn!(%target, %pathname, %lineno, python_fields = %fields, "{}", message )
-                }
-                tracing::Level::INFO => {
-                    tracing::info!(%target, %pathname, %lineno, python_fields = %fields, "{}", message )
-                }
-                tracing::Level::DEBUG => {
-                    tracing::debug!(%target, %pathname, %lineno, python_fields = %fields, "{}", message )
-                }
-                tracing::Level::TRACE => {
-                    tracing::trace!(%target, %pathname, %lineno, python_fields = %fields, "{}", message )
-                }
-            }
-        }
-        #[cfg(not(feature = "kv-common"))]
-        {
-            match level {
-                tracing::Level::ERROR => {
-                    tracing::event!(tracing::Level::ERROR, %target, %pathname, %lineno, "{}", message)
-                }
-                tracing::Level::WARN => {
-                    tracing::event!(tracing::Level::WARN, %target, %pathname, %lineno, "{}", message)
-                }
-                tracing::Level::INFO => {
-                    tracing::event!(tracing::Level::INFO, %target, %pathname, %lineno, "{}", message)
-                }
-                tracing::Level::DEBUG => {
-                    tracing::event!(tracing::Level::DEBUG, %target, %pathname, %lineno, "{}", message)
-                }
-                tracing::Level::TRACE => {
-                    tracing::event!(tracing::Level::TRACE, %target, %pathname, %lineno, "{}", message)
-                }
+#[cfg(feature = "tracing")]
+fn emit_tracing(
+    context: &record::RecordContext,
+    target: &str,
+    message: &str,
+    level: PyLevel,
+    #[allow(unused_variables)] kv_args: KvArgs,
+) {
+    use tracing::field::{display, Value};
+
+    let location = tracing_location(target, level.into(), &context.location);
+
+    // `kv::extract` sorts key-values by key, so records with the same keys share a callsite.
+    #[cfg(feature = "tracing-kv")]
+    let (callsite, kv_args) = {
+        let fields = record::FIELDS
+            .iter()
+            .copied()
+            .chain(kv_args.iter().map(|(key, _)| key.as_str()))
+            .collect::<Vec<_>>();
+        match callsite::try_callsite(&location, &fields) {
+            Some(callsite) => (callsite, kv_args),
+            // Too many keys, or sets of keys at this location: record them as a single map instead.
+            None => {
+                let fields = [record::FIELDS, &[callsite::OVERFLOW_FIELD]].concat();
+                let kv_args = vec![(
+                    callsite::OVERFLOW_FIELD.to_string(),
+                    value::PyValue::Map(kv_args),
+                )];
+                (callsite::callsite(&location, &fields), kv_args)
             }
         }
+    };
+    #[cfg(not(feature = "tracing-kv"))]
+    let callsite = callsite::callsite(&location, record::FIELDS);
+
+    if !callsite.is_enabled() {
+        return;
     }
-    Ok(())
+
+    // Optional fields are only recorded when `Some`.
+    let exception = context.exception.as_ref();
+    let exception_type = exception.map(|e| e.type_name.as_str());
+    let exception_message = exception.map(|e| e.message.as_str());
+    let exception_stacktrace = exception.map(|e| e.stacktrace.as_str());
+    let python_level = context.python_level.as_ref();
+    let python_levelname = python_level.map(|(name, _)| name.as_str());
+    let python_levelno = python_level.map(|(_, levelno)| *levelno);
+
+    fn optional<T: Value>(value: &Option<T>) -> Option<&dyn Value> {
+        value.as_ref().map(|value| value as &dyn Value)
+    }
+
+    // In the order of `record::FIELDS`.
+    let message = display(message);
+    let values = [
+        Some(&message as &dyn Value),
+        optional(&context.func_name),
+        optional(&exception_type),
+        optional(&exception_message),
+        optional(&exception_stacktrace),
+        optional(&context.stack_info),
+        optional(&context.exc_text),
+        optional(&python_levelname),
+        optional(&python_levelno),
+    ];
+
+    #[cfg(feature = "tracing-kv")]
+    let extra_values: Vec<_> = kv_args
+        .iter()
+        .map(|(_, value)| value.as_tracing())
+        .collect();
+    #[cfg(feature = "tracing-kv")]
+    let values: Vec<Option<&dyn Value>> = values
+        .into_iter()
+        .chain(extra_values.iter().map(|value| value.as_deref()))
+        .collect();
+
+    callsite.dispatch(&values);
 }
 
 /// Registers the host_log function in rust as the event handler for Python's logging logger
 /// This function needs to be called from within a pyo3 context as early as possible to ensure logging messages
 /// arrive to the rust consumer.
+///
+/// Equivalent to `PyLoggerConfig::new(target).setup_logging(py)`.
 pub fn setup_logging(py: Python, target: &str) -> PyResult<()> {
-    let logging = py.import("logging")?;
-
-    logging.setattr("host_log", wrap_pyfunction!(host_log, &logging)?)?;
-
-    #[allow(clippy::uninlined_format_args)]
-    let code = CString::new(format!(
-        r#"
-class HostHandler(Handler):
-	def __init__(self, level=0):
-		super().__init__(level=level)
-
-	def emit(self, record: LogRecord):
-		host_log(record, "{}")
-
-oldBasicConfig = basicConfig
-def basicConfig(*pargs, **kwargs):
-    if "handlers" not in kwargs:
-        kwargs["handlers"] = [HostHandler()]
-    return oldBasicConfig(*pargs, **kwargs)
-"#,
-        target
-    ))?;
-
-    py.run(&code, Some(&logging.dict()), None)?;
-
-    let all = logging.index()?;
-    all.append("HostHandler")?;
-
-    Ok(())
+    PyLoggerConfig::new(target).setup_logging(py)
 }
@@ -0,0 +1,124 @@
+//! A `log::Log` implementation that forwards Rust records into Python's `logging` module.
+//!
+//! This is the reverse direction of the `HostHandler`: handlers configured on the Python side (file handlers,
+//! error reporting SDKs, ...) also receive the records logged by the host application.
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+use crate::dispatch::{self, PyRecord};
+use crate::guard;
+use crate::PyLevel;
+
+/// Forwards `log` records to `logging.getLogger(target).handle()`.
+///
+/// The Rust target becomes the Python logger name (`foo::bar` -> `foo.bar`), and the level, file and line
+/// populate `levelno`, `pathname` and `lineno` of the `LogRecord`. With the `kv` feature, key-values are
+/// set as record attributes, the same way Python's `extra` does.
+///
+/// Records are never sent back to Rust: records that Python logged through the `HostHandler` are dropped
+/// and counted by [crate::dropped_records].
+///
+/// # Example
+/// ```no_run
+/// pyo3_pylogger::PythonLogger::new()
+///     .with_max_level(log::LevelFilter::Info)
+///     .init()
+///     .unwrap();
+/// ```
+#[derive(Debug)]
+pub struct PythonLogger {
+    max_level: LevelFilter,
+}
+
+impl Default for PythonLogger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PythonLogger {
+    /// Creates a logger forwarding records of every level.
+    pub fn new() -> Self {
+        Self {
+            max_level: LevelFilter::Trace,
+        }
+    }
+
+    /// Only forward records at or above `max_level`.
+    pub fn with_max_level(mut self, max_level: LevelFilter) -> Self {
+        self.max_level = max_level;
+        self
+    }
+
+    /// Installs this logger as the global `log` logger.
+    pub fn init(self) -> Result<(), log::SetLoggerError> {
+        log::set_max_level(self.max_level);
+        log::set_logger(Box::leak(Box::new(self)))
+    }
+}
+
+impl Log for PythonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.max_level && guard::crossing().is_none()
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() > self.max_level {
+            return;
+        }
+        if guard::crossing().is_some() {
+            guard::record_dropped();
+            return;
+        }
+
+        dispatch::dispatch(PyRecord {
+            name: PyRecord::logger_name(record.target()),
+            levelno: PyLevel::from(record.level()).levelno(),
+            pathname: record.file().map(str::to_string),
+            lineno: record.line(),
+            message: record.args().to_string(),
+            #[cfg(feature = "kv")]
+            extra: extra(record),
+            #[cfg(not(feature = "kv"))]
+            extra: Vec::new(),
+        });
+    }
+
+    fn flush(&self) {
+        dispatch::flush();
+    }
+}
+
+/// Collects the record's key-values, keeping numbers and booleans typed.
+#[cfg(feature = "kv")]
+fn extra(record: &Record) -> Vec<(String, dispatch::ExtraValue)> {
+    use dispatch::ExtraValue;
+
+    struct Collect(Vec<(String, ExtraValue)>);
+
+    impl<'kvs> log::kv::VisitSource<'kvs> for Collect {
+        fn visit_pair(
+            &mut self,
+            key: log::kv::Key<'kvs>,
+            value: log::kv::Value<'kvs>,
+        ) -> Result<(), log::kv::Error> {
+            let value = if let Some(v) = value.to_bool() {
+                ExtraValue::Bool(v)
+            } else if let Some(v) = value.to_i64() {
+                ExtraValue::I64(v)
+            } else if let Some(v) = value.to_u64() {
+                ExtraValue::U64(v)
+            } else if let Some(v) = value.to_f64() {
+                ExtraValue::F64(v)
+            } else {
+                ExtraValue::Str(value.to_string())
+            };
+            self.0.push((key.to_string(), value));
+            Ok(())
+        }
+    }
+
+    let mut collect = Collect(Vec::new());
+    let _ = record.key_values().visit(&mut collect);
+    collect.0
+}
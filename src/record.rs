@@ -0,0 +1,260 @@
+//! Information read from a Python `LogRecord` in addition to its message, level and target.
+//!
+//! Unlike the key-values found by `kv::find_kv_args`, these come from standard `LogRecord` attributes
+//! and are forwarded under dedicated names rather than mixed with the user's `extra`.
+
+use pyo3::prelude::*;
+
+use crate::redact::Redaction;
+use crate::PyLoggerConfig;
+
+/// The message and the names [RecordContext] is forwarded under, as key-values with the `kv` feature and as
+/// fields under `tracing`. Key-values from `extra` are renamed rather than shadow them.
+///
+/// This is also the order in which `tracing` field values are passed to the event callsite.
+#[cfg_attr(not(any(feature = "kv-common", feature = "tracing")), allow(dead_code))]
+pub(crate) const FIELDS: &[&str] = &[
+    "message",
+    "py.funcName",
+    "exception.type",
+    "exception.message",
+    "exception.stacktrace",
+    "stack_info",
+    "exc_text",
+    "py.levelname",
+    "py.levelno",
+];
+
+/// The exception attached to a record through `exc_info`, e.g. by `logging.exception()`.
+#[cfg_attr(not(any(feature = "kv", feature = "tracing")), allow(dead_code))]
+pub(crate) struct ExceptionInfo {
+    /// The exception type's name, e.g. `ValueError`.
+    pub type_name: String,
+    /// `str()` of the exception.
+    pub message: String,
+    /// The formatted traceback, as printed by `traceback.print_exception()`.
+    pub stacktrace: String,
+}
+
+impl ExceptionInfo {
+    /// Reads `record.exc_info`, returning `None` if no exception is attached to the record.
+    pub(crate) fn from_record(record: &Bound<'_, PyAny>) -> PyResult<Option<Self>> {
+        let exc_info = record.getattr("exc_info")?;
+        if exc_info.is_none() {
+            return Ok(None);
+        }
+
+        // `logging.exception()` outside of an `except` block records `(None, None, None)`.
+        let (exc_type, exc_value, exc_traceback) =
+            exc_info.extract::<(Bound<'_, PyAny>, Bound<'_, PyAny>, Bound<'_, PyAny>)>()?;
+        if exc_type.is_none() {
+            return Ok(None);
+        }
+
+        // A broken exception (e.g. whose `__str__` raises) must not make the caller's logging call fail, so
+        // failures fall back to placeholders, as `traceback` itself does.
+        let type_name = exc_type
+            .getattr("__name__")
+            .and_then(|name| name.extract::<String>())
+            .unwrap_or_else(|_| "<unknown>".to_string());
+        let message = exc_value
+            .str()
+            .map(|message| message.to_string())
+            .unwrap_or_else(|_| "<exception str() failed>".to_string());
+        let stacktrace = format_exception(&exc_type, &exc_value, &exc_traceback)
+            .unwrap_or_else(|_| format!("{type_name}: {message}"));
+
+        Ok(Some(Self {
+            type_name,
+            message,
+            stacktrace: stacktrace.trim_end().to_string(),
+        }))
+    }
+}
+
+/// The traceback of an exception, as printed by `traceback.print_exception()`.
+fn format_exception(
+    exc_type: &Bound<'_, PyAny>,
+    exc_value: &Bound<'_, PyAny>,
+    exc_traceback: &Bound<'_, PyAny>,
+) -> PyResult<String> {
+    exc_type
+        .py()
+        .import("traceback")?
+        .call_method1("format_exception", (exc_type, exc_value, exc_traceback))?
+        .try_iter()?
+        .map(|line| line.map(|line| line.to_string()))
+        .collect()
+}
+
+/// Where a record was logged, read before deciding whether the record is enabled.
+pub(crate) struct SourceLocation {
+    pub pathname: String,
+    pub lineno: u32,
+    /// The Python module as a Rust path, e.g. `mypkg::worker`, see [module_path].
+    pub module_path: String,
+}
+
+impl SourceLocation {
+    pub(crate) fn from_record(record: &Bound<'_, PyAny>, logger_name: &str) -> PyResult<Self> {
+        let module = record.getattr("module")?.extract::<String>()?;
+        Ok(Self {
+            pathname: record.getattr("pathname")?.extract()?,
+            lineno: record.getattr("lineno")?.extract()?,
+            module_path: module_path(logger_name, &module),
+        })
+    }
+}
+
+/// Everything forwarded from a `LogRecord` besides its message, level, target and `extra`.
+pub(crate) struct RecordContext {
+    pub location: SourceLocation,
+    /// `record.funcName`, the function the record was logged from.
+    #[cfg_attr(not(any(feature = "kv", feature = "tracing")), allow(dead_code))]
+    pub func_name: Option<String>,
+    pub exception: Option<ExceptionInfo>,
+    /// `record.stack_info`, if enabled by [PyLoggerConfig::with_stack_info].
+    pub stack_info: Option<String>,
+    /// `record.exc_text`, if enabled by [PyLoggerConfig::with_stack_info].
+    pub exc_text: Option<String>,
+    /// `record.levelname` and `record.levelno`, if enabled by [PyLoggerConfig::with_python_level].
+    #[cfg_attr(not(any(feature = "kv", feature = "tracing")), allow(dead_code))]
+    pub python_level: Option<(String, i64)>,
+}
+
+impl RecordContext {
+    pub(crate) fn from_record(
+        record: &Bound<'_, PyAny>,
+        config: &PyLoggerConfig,
+        location: SourceLocation,
+        levelno: i64,
+    ) -> PyResult<Self> {
+        let (stack_info, exc_text) = if config.stack_info() {
+            (
+                record.getattr("stack_info")?.extract()?,
+                record.getattr("exc_text")?.extract()?,
+            )
+        } else {
+            (None, None)
+        };
+        let python_level = if config.python_level() {
+            Some((record.getattr("levelname")?.to_string(), levelno))
+        } else {
+            None
+        };
+
+        let mut context = Self {
+            location,
+            func_name: record.getattr("funcName")?.extract()?,
+            exception: ExceptionInfo::from_record(record)?,
+            stack_info,
+            exc_text,
+            python_level,
+        };
+        context.redact(config.redaction());
+        Ok(context)
+    }
+
+    /// Redacts the texts that may contain sensitive data: exceptions, `stack_info` and `exc_text`.
+    fn redact(&mut self, redaction: &Redaction) {
+        if let Some(exception) = &mut self.exception {
+            redaction.redact_string(&mut exception.message);
+            redaction.redact_string(&mut exception.stacktrace);
+        }
+        for text in [&mut self.stack_info, &mut self.exc_text]
+            .into_iter()
+            .flatten()
+        {
+            redaction.redact_string(text);
+        }
+    }
+
+    /// Appends the context to `message`, the same way Python's `logging.Formatter` appends tracebacks.
+    ///
+    /// Used when the context can't be forwarded as key-values. Both `message` and the context are expected to
+    /// be redacted already.
+    #[cfg(all(feature = "log", not(feature = "kv")))]
+    pub(crate) fn append_to(&self, message: &str) -> String {
+        let mut message = message.to_string();
+        let exception = self.exception.as_ref().map(|e| e.stacktrace.as_str());
+        for text in [
+            exception,
+            self.exc_text.as_deref(),
+            self.stack_info.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            message.push('\n');
+            message.push_str(text);
+        }
+        message
+    }
+}
+
+/// Derives a Rust module path from a record's logger name and `module` attribute.
+///
+/// `record.module` only holds the file name without its extension, so the full dotted path is taken from
+/// the logger name when it follows the `logging.getLogger(__name__)` convention, i.e. when its last segment
+/// is the module (or the module is a package's `__init__`).
+fn module_path(logger_name: &str, module: &str) -> String {
+    let last_segment = logger_name.rsplit('.').next().unwrap_or_default();
+    let path = if !last_segment.is_empty() && (last_segment == module || module == "__init__") {
+        logger_name
+    } else {
+        module
+    };
+    path.replace('.', "::")
+}
+
+#[cfg(feature = "kv")]
+impl log::kv::Source for RecordContext {
+    fn visit<'kvs>(
+        &'kvs self,
+        visitor: &mut dyn log::kv::VisitSource<'kvs>,
+    ) -> Result<(), log::kv::Error> {
+        if let Some(func_name) = &self.func_name {
+            visitor.visit_pair(
+                log::kv::Key::from_str("py.funcName"),
+                log::kv::Value::from(func_name.as_str()),
+            )?;
+        }
+        if let Some(exception) = &self.exception {
+            visitor.visit_pair(
+                log::kv::Key::from_str("exception.type"),
+                log::kv::Value::from(exception.type_name.as_str()),
+            )?;
+            visitor.visit_pair(
+                log::kv::Key::from_str("exception.message"),
+                log::kv::Value::from(exception.message.as_str()),
+            )?;
+            visitor.visit_pair(
+                log::kv::Key::from_str("exception.stacktrace"),
+                log::kv::Value::from(exception.stacktrace.as_str()),
+            )?;
+        }
+        if let Some(stack_info) = &self.stack_info {
+            visitor.visit_pair(
+                log::kv::Key::from_str("stack_info"),
+                log::kv::Value::from(stack_info.as_str()),
+            )?;
+        }
+        if let Some(exc_text) = &self.exc_text {
+            visitor.visit_pair(
+                log::kv::Key::from_str("exc_text"),
+                log::kv::Value::from(exc_text.as_str()),
+            )?;
+        }
+        if let Some((levelname, levelno)) = &self.python_level {
+            visitor.visit_pair(
+                log::kv::Key::from_str("py.levelname"),
+                log::kv::Value::from(levelname.as_str()),
+            )?;
+            visitor.visit_pair(
+                log::kv::Key::from_str("py.levelno"),
+                log::kv::Value::from(*levelno),
+            )?;
+        }
+        Ok(())
+    }
+}
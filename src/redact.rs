@@ -0,0 +1,332 @@
+//! Redaction of sensitive data from messages and key-values before they reach the Rust backend.
+//!
+//! [RedactRule]s either redact whole key-values by key name, or rewrite text wherever it appears: in the
+//! formatted message, in exceptions, `stack_info` and `exc_text`, and in key-values, including those nested in
+//! `dict` and `list` values.
+
+use std::borrow::Cow;
+use std::sync::Arc;
+
+#[cfg(feature = "kv-common")]
+use crate::value::PyValue;
+
+/// The text redacted data is replaced with, unless set with [crate::PyLoggerConfig::with_redaction_replacement].
+pub(crate) const DEFAULT_REPLACEMENT: &str = "[REDACTED]";
+
+/// A rule selecting data to redact, added with [crate::PyLoggerConfig::with_redact_rule].
+///
+/// # Example
+/// ```no_run
+/// use pyo3_pylogger::{PyLoggerConfig, RedactRule};
+///
+/// PyLoggerConfig::new("my_application")
+///     .with_redact_rule(RedactRule::key("*password*"))
+///     .with_redact_rule(RedactRule::key("authorization"))
+///     .register();
+/// ```
+#[derive(Clone)]
+pub struct RedactRule(Rule);
+
+type RedactFn = dyn for<'a> Fn(&'a str) -> Cow<'a, str> + Send + Sync;
+
+#[derive(Clone)]
+enum Rule {
+    /// A lowercase key pattern.
+    #[cfg_attr(not(feature = "kv-common"), allow(dead_code))]
+    Key(String),
+    #[cfg(feature = "regex")]
+    Value(regex::Regex),
+    Custom(Arc<RedactFn>),
+}
+
+impl RedactRule {
+    /// Key-values whose key matches `pattern` are replaced entirely, including keys of nested `dict`s.
+    ///
+    /// A `*` in the pattern matches any sequence of characters, and the match ignores case, so `*password*`
+    /// matches `password`, `db_password` and `PasswordHash`.
+    pub fn key(pattern: impl Into<String>) -> Self {
+        Self(Rule::Key(pattern.into().to_lowercase()))
+    }
+
+    /// Every match of `regex` in the message, exceptions, `stack_info`, `exc_text` and key-values is replaced.
+    ///
+    /// Integer key-values are matched in their decimal form, and become strings when redacted. With `value(r"eyJ[\w-]*\.[\w-]*\.[\w-]*")`, JSON web tokens are redacted wherever they are logged.
+    #[cfg(feature = "regex")]
+    pub fn value(regex: &str) -> Result<Self, regex::Error> {
+        Ok(Self(Rule::Value(regex::Regex::new(regex)?)))
+    }
+
+    /// The message, exceptions, `stack_info`, `exc_text` and every string key-value are replaced with the
+    /// text returned by `f`.
+    ///
+    /// The replacement configured with [crate::PyLoggerConfig::with_redaction_replacement] is not used.
+    pub fn custom(f: impl for<'a> Fn(&'a str) -> Cow<'a, str> + Send + Sync + 'static) -> Self {
+        Self(Rule::Custom(Arc::new(f)))
+    }
+}
+
+impl std::fmt::Debug for RedactRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            Rule::Key(pattern) => f.debug_tuple("Key").field(pattern).finish(),
+            #[cfg(feature = "regex")]
+            Rule::Value(regex) => f.debug_tuple("Value").field(regex).finish(),
+            Rule::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+/// The redaction rules of a configuration.
+#[derive(Clone, Debug)]
+pub(crate) struct Redaction {
+    pub rules: Vec<RedactRule>,
+    pub replacement: String,
+}
+
+impl Default for Redaction {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            replacement: DEFAULT_REPLACEMENT.to_string(),
+        }
+    }
+}
+
+impl Redaction {
+    /// Applies the value and custom rules to `text`, in the order they were added.
+    pub(crate) fn redact_text<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        self.apply(text, true)
+    }
+
+    /// Redacts `text` in place, see [Redaction::redact_text].
+    pub(crate) fn redact_string(&self, text: &mut String) {
+        if let Cow::Owned(redacted) = self.redact_text(text) {
+            *text = redacted;
+        }
+    }
+
+    fn apply<'a>(&self, text: &'a str, custom: bool) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for rule in &self.rules {
+            let redacted = match &rule.0 {
+                Rule::Key(_) => continue,
+                #[cfg(feature = "regex")]
+                Rule::Value(regex) => match regex.replace_all(&text, self.replacement.as_str()) {
+                    Cow::Borrowed(_) => continue,
+                    Cow::Owned(redacted) => redacted,
+                },
+                Rule::Custom(_) if !custom => continue,
+                Rule::Custom(f) => match f(&text) {
+                    Cow::Borrowed(redacted) if redacted == text.as_ref() => continue,
+                    redacted => redacted.into_owned(),
+                },
+            };
+            text = Cow::Owned(redacted);
+        }
+        text
+    }
+
+    #[cfg(feature = "kv-common")]
+    fn redacts_key(&self, key: &str) -> bool {
+        let key = key.to_lowercase();
+        self.rules.iter().any(|rule| match &rule.0 {
+            Rule::Key(pattern) => crate::kv::glob_match(pattern, &key),
+            _ => false,
+        })
+    }
+
+    /// Redacts the key-value `key`, either entirely or the text it contains.
+    #[cfg(feature = "kv-common")]
+    pub(crate) fn redact_value(&self, key: &str, value: &mut PyValue) {
+        if self.redacts_key(key) {
+            *value = PyValue::Str(self.replacement.clone());
+            return;
+        }
+        self.redact_nested(value);
+    }
+
+    #[cfg(feature = "kv-common")]
+    fn redact_nested(&self, value: &mut PyValue) {
+        match value {
+            PyValue::Str(text) | PyValue::Other(text) => self.redact_string(text),
+            // Only value rules apply to numbers: custom rules are written for strings.
+            #[cfg(feature = "regex")]
+            PyValue::Int(_) | PyValue::UInt(_) | PyValue::BigInt(_) if self.has_value_rules() => {
+                if let Cow::Owned(redacted) = self.apply(&value.to_string(), false) {
+                    *value = PyValue::Str(redacted);
+                }
+            }
+            PyValue::List(items) => items.iter_mut().for_each(|item| self.redact_nested(item)),
+            PyValue::Map(entries) => entries
+                .iter_mut()
+                .for_each(|(key, value)| self.redact_value(key, value)),
+            _ => {}
+        }
+    }
+
+    #[cfg(all(feature = "kv-common", feature = "regex"))]
+    fn has_value_rules(&self) -> bool {
+        self.rules
+            .iter()
+            .any(|rule| matches!(rule.0, Rule::Value(_)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redaction(rules: impl IntoIterator<Item = RedactRule>) -> Redaction {
+        Redaction {
+            rules: rules.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    fn mask_digits() -> RedactRule {
+        RedactRule::custom(|text| {
+            if text.contains(|c: char| c.is_ascii_digit()) {
+                Cow::Owned(text.replace(|c: char| c.is_ascii_digit(), "#"))
+            } else {
+                Cow::Borrowed(text)
+            }
+        })
+    }
+
+    #[test]
+    fn text_without_rules() {
+        let redaction = Redaction::default();
+        assert!(matches!(
+            redaction.redact_text("secret"),
+            Cow::Borrowed("secret")
+        ));
+    }
+
+    #[test]
+    fn text_ignores_key_rules() {
+        let redaction = redaction([RedactRule::key("*")]);
+        assert!(matches!(
+            redaction.redact_text("secret"),
+            Cow::Borrowed("secret")
+        ));
+    }
+
+    #[test]
+    fn text_custom() {
+        let redaction = redaction([mask_digits()]);
+        assert_eq!(redaction.redact_text("pin 1234"), "pin ####");
+        assert!(matches!(redaction.redact_text("no pin"), Cow::Borrowed(_)));
+
+        let mut text = "pin 1234".to_string();
+        redaction.redact_string(&mut text);
+        assert_eq!(text, "pin ####");
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn text_value() {
+        let mut redaction = redaction([RedactRule::value(r"eyJ[\w-]*\.[\w-]*\.[\w-]*").unwrap()]);
+        assert_eq!(
+            redaction.redact_text("token eyJhbGc.eyJzdWI.c2ln, again eyJa.b.c"),
+            "token [REDACTED], again [REDACTED]"
+        );
+        assert!(matches!(
+            redaction.redact_text("no token"),
+            Cow::Borrowed(_)
+        ));
+
+        redaction.replacement = "***".to_string();
+        assert_eq!(redaction.redact_text("eyJa.b.c"), "***");
+        assert!(RedactRule::value("(").is_err());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn text_rules_apply_in_order() {
+        let redaction = redaction([RedactRule::value(r"\d{16}").unwrap(), mask_digits()]);
+        assert_eq!(
+            redaction.redact_text("card 4111111111111111, pin 1234"),
+            "card [REDACTED], pin ####"
+        );
+    }
+
+    #[cfg(feature = "kv-common")]
+    #[test]
+    fn value_by_key() {
+        let redaction = redaction([
+            RedactRule::key("*password*"),
+            RedactRule::key("Authorization"),
+        ]);
+        for key in ["password", "DB_Password", "authorization"] {
+            let mut value = PyValue::Int(1);
+            redaction.redact_value(key, &mut value);
+            assert_eq!(value, PyValue::Str("[REDACTED]".to_string()), "{key}");
+        }
+        let mut value = PyValue::Str("kept".to_string());
+        redaction.redact_value("user", &mut value);
+        assert_eq!(value, PyValue::Str("kept".to_string()));
+    }
+
+    #[cfg(feature = "kv-common")]
+    #[test]
+    fn value_nested() {
+        let redaction = redaction([RedactRule::key("password"), mask_digits()]);
+        let mut value = PyValue::Map(vec![
+            ("password".to_string(), PyValue::Str("hunter2".to_string())),
+            (
+                "tags".to_string(),
+                PyValue::List(vec![
+                    PyValue::Str("pin 1234".to_string()),
+                    PyValue::Other("<object 42>".to_string()),
+                    PyValue::Map(vec![("password".to_string(), PyValue::None)]),
+                ]),
+            ),
+            ("count".to_string(), PyValue::Int(1234)),
+        ]);
+        redaction.redact_value("user", &mut value);
+        assert_eq!(
+            value,
+            PyValue::Map(vec![
+                (
+                    "password".to_string(),
+                    PyValue::Str("[REDACTED]".to_string())
+                ),
+                (
+                    "tags".to_string(),
+                    PyValue::List(vec![
+                        PyValue::Str("pin ####".to_string()),
+                        PyValue::Other("<object ##>".to_string()),
+                        PyValue::Map(vec![(
+                            "password".to_string(),
+                            PyValue::Str("[REDACTED]".to_string())
+                        )]),
+                    ]),
+                ),
+                // Custom rules don't apply to numbers.
+                ("count".to_string(), PyValue::Int(1234)),
+            ])
+        );
+    }
+
+    #[cfg(all(feature = "kv-common", feature = "regex"))]
+    #[test]
+    fn value_numbers() {
+        let redaction = redaction([RedactRule::value(r"^\d{16}$").unwrap()]);
+        let cases = [
+            (PyValue::Int(4111111111111111), true),
+            (PyValue::UInt(4111111111111111), true),
+            (PyValue::BigInt(4111111111111111), true),
+            (PyValue::Int(42), false),
+            (PyValue::Float(4111111111111111.0), false),
+        ];
+        for (mut value, redacted) in cases {
+            let original = value.clone();
+            redaction.redact_value("card", &mut value);
+            if redacted {
+                assert_eq!(value, PyValue::Str("[REDACTED]".to_string()));
+            } else {
+                assert_eq!(value, original);
+            }
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! Keeps Python's `logging` levels in line with the maximum level enabled on the Rust side.
+//!
+//! Without this, Python creates every record and calls `host_log` with it, only for the Rust logger to
+//! discard it. With [crate::PyLoggerConfig::with_level_sync], the root logger and the `HostHandler`s are
+//! set to the lowest Python level that maps to an enabled Rust level, so disabled records are dropped by
+//! Python before their message is formatted.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use pyo3::prelude::*;
+
+use crate::{Backend, PyLevel, PyLoggerConfig};
+
+/// The level most recently applied to Python by a registration, see [LevelSync::changed].
+#[derive(Debug, Default)]
+pub(crate) struct LevelSync(AtomicU8);
+
+impl LevelSync {
+    /// Records `level` as applied, returning `false` if it already was.
+    fn changed(&self, level: PyLevel) -> bool {
+        let encoded = level as u8 + 1;
+        self.0.swap(encoded, Ordering::Relaxed) != encoded
+    }
+}
+
+/// The most verbose level enabled by the Rust side for `backend`, or `None` if everything is disabled.
+fn rust_max_level(backend: Backend) -> Option<PyLevel> {
+    #[cfg(feature = "log")]
+    let log_level = || log::max_level().to_level().map(PyLevel::from);
+    #[cfg(feature = "tracing")]
+    let tracing_level = || {
+        tracing::level_filters::LevelFilter::current()
+            .into_level()
+            .map(PyLevel::from)
+    };
+
+    match backend {
+        #[cfg(feature = "log")]
+        Backend::Log => log_level(),
+        #[cfg(feature = "tracing")]
+        Backend::Tracing => tracing_level(),
+        #[cfg(all(feature = "log", feature = "tracing"))]
+        Backend::Both => match (log_level(), tracing_level()) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        },
+    }
+}
+
+/// Applies the Rust maximum level to Python if it changed since the last call for this registration.
+///
+/// Nothing is changed while the Rust side has everything disabled, as this usually means the Rust logger
+/// hasn't been installed yet. Nothing is changed either if the Python level can't be derived from the level
+/// mapping, e.g. with [PyLoggerConfig::with_level_mapper].
+pub(crate) fn sync(py: Python<'_>, config: &PyLoggerConfig) -> PyResult<()> {
+    let Some(level) = rust_max_level(config.backend()) else {
+        return Ok(());
+    };
+    if !config.level_sync_state().changed(level) {
+        return Ok(());
+    }
+    let Some(levelno) = config.level_mapping().threshold(level) else {
+        return Ok(());
+    };
+
+    py.import("logging")?
+        .call_method1("_pyo3_pylogger_sync_level", (config.target(), levelno))?;
+    Ok(())
+}
+
+/// Applies the current Rust maximum level to Python for every registration with level sync enabled.
+///
+/// Registrations re-sync automatically when the `HostHandler` receives a record, but records that Python
+/// already filters out never reach it: call this after making the Rust side more verbose, e.g. after
+/// `log::set_max_level` or reloading a `tracing` filter.
+pub fn sync_levels() {
+    Python::attach(|py| {
+        for config in crate::config::registered() {
+            if config.level_sync() {
+                if let Err(e) = sync(py, &config) {
+                    e.write_unraisable(py, None);
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,313 @@
+//! Mapping of Python logger names to Rust targets.
+//!
+//! By default, the logger `foo.bar` becomes the target `{target}::foo::bar`, where `{target}` is the
+//! registration target, and the root logger becomes `{target}`. [TargetMode] changes this for all loggers,
+//! and [TargetRule]s for selected loggers, so that e.g. third-party Python libraries line up with existing
+//! `RUST_LOG` directives.
+
+use std::borrow::Cow;
+use std::sync::Arc;
+
+/// How Python logger names are turned into Rust targets, set with [crate::PyLoggerConfig::with_target_mode].
+///
+/// In every mode, `.` separators are replaced with `::`, as libraries (ex: `tracing_subscriber::filter::Directive`)
+/// expect Rust-style targets. Loggers matched by a [TargetRule] producing a complete target are not affected.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum TargetMode {
+    /// `foo.bar` becomes `{target}::foo::bar`, the root logger becomes `{target}`.
+    #[default]
+    Prefixed,
+    /// `foo.bar` becomes `foo::bar`, the root logger becomes `{target}`.
+    Bare,
+    /// `foo.bar` becomes `{prefix}::foo::bar` and the root logger becomes `{prefix}`, for the given prefix.
+    Custom(String),
+}
+
+/// A rule rewriting the Rust target of matching Python loggers, added with
+/// [crate::PyLoggerConfig::with_target_rule].
+///
+/// Rules are tried in the order they were added and the first matching rule applies.
+///
+/// # Example
+/// ```no_run
+/// use pyo3_pylogger::{PyLoggerConfig, TargetRule};
+///
+/// PyLoggerConfig::new("my_application")
+///     // `urllib3.connectionpool` -> `http_client::connectionpool`
+///     .with_target_rule(TargetRule::rename("urllib3", "http_client"))
+///     // `my_application.worker` -> `my_application::worker`
+///     .with_target_rule(TargetRule::strip_prefix("my_application"))
+///     .register();
+/// ```
+#[derive(Clone)]
+pub struct TargetRule(Rule);
+
+type TargetFn = dyn for<'a> Fn(&'a str) -> Cow<'a, str> + Send + Sync;
+
+#[derive(Clone)]
+enum Rule {
+    Rename {
+        from: String,
+        to: String,
+    },
+    StripPrefix(String),
+    #[cfg(feature = "regex")]
+    Regex {
+        regex: regex::Regex,
+        replacement: String,
+    },
+    Custom(Arc<TargetFn>),
+}
+
+/// The outcome of a matching rule.
+enum Mapped<'a> {
+    /// The complete Rust target.
+    Target(Cow<'a, str>),
+    /// A new logger name, turned into a target according to the [TargetMode].
+    Name(&'a str),
+}
+
+impl TargetRule {
+    /// Loggers named `from`, or children of it, use the target `to` in place of `from`.
+    ///
+    /// With `rename("urllib3", "http_client")`, `urllib3` becomes `http_client` and
+    /// `urllib3.connectionpool` becomes `http_client::connectionpool`. The registration target is not
+    /// prepended.
+    pub fn rename(from: impl Into<String>, to: impl Into<String>) -> Self {
+        Self(Rule::Rename {
+            from: from.into(),
+            to: to.into(),
+        })
+    }
+
+    /// Loggers named `prefix`, or children of it, have `prefix` removed from their name before being placed
+    /// under the registration target, according to the [TargetMode].
+    ///
+    /// With the registration target `app` and `strip_prefix("app")`, `app.worker` becomes `app::worker`
+    /// instead of `app::app::worker`, and `app` becomes `app`.
+    pub fn strip_prefix(prefix: impl Into<String>) -> Self {
+        Self(Rule::StripPrefix(prefix.into()))
+    }
+
+    /// Loggers whose name matches `regex` use the target produced by replacing the match with `replacement`,
+    /// which can refer to capture groups as in [regex::Regex::replace]. The registration target is not
+    /// prepended.
+    ///
+    /// With `regex(r"^celery\.(\w+).*", "tasks::$1")`, `celery.worker.consumer` becomes `tasks::worker`.
+    #[cfg(feature = "regex")]
+    pub fn regex(regex: &str, replacement: impl Into<String>) -> Result<Self, regex::Error> {
+        Ok(Self(Rule::Regex {
+            regex: regex::Regex::new(regex)?,
+            replacement: replacement.into(),
+        }))
+    }
+
+    /// Every logger reaching this rule uses the target returned by `f` for its name, as is.
+    pub fn custom(f: impl for<'a> Fn(&'a str) -> Cow<'a, str> + Send + Sync + 'static) -> Self {
+        Self(Rule::Custom(Arc::new(f)))
+    }
+
+    fn apply<'a>(&self, name: &'a str) -> Option<Mapped<'a>> {
+        match &self.0 {
+            Rule::Rename { from, to } => {
+                let rest = strip_logger_prefix(name, from)?;
+                Some(Mapped::Target(Cow::Owned(rust_path(&format!(
+                    "{to}{rest}"
+                )))))
+            }
+            Rule::StripPrefix(prefix) => {
+                let rest = strip_logger_prefix(name, prefix)?;
+                Some(Mapped::Name(rest.strip_prefix('.').unwrap_or(rest)))
+            }
+            #[cfg(feature = "regex")]
+            Rule::Regex { regex, replacement } => {
+                if !regex.is_match(name) {
+                    return None;
+                }
+                let target = regex.replace(name, replacement.as_str());
+                Some(Mapped::Target(Cow::Owned(rust_path(&target))))
+            }
+            Rule::Custom(f) => Some(Mapped::Target(f(name))),
+        }
+    }
+}
+
+impl std::fmt::Debug for TargetRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            Rule::Rename { from, to } => f
+                .debug_struct("Rename")
+                .field("from", from)
+                .field("to", to)
+                .finish(),
+            Rule::StripPrefix(prefix) => f.debug_tuple("StripPrefix").field(prefix).finish(),
+            #[cfg(feature = "regex")]
+            Rule::Regex { regex, replacement } => f
+                .debug_struct("Regex")
+                .field("regex", regex)
+                .field("replacement", replacement)
+                .finish(),
+            Rule::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+/// Returns the rest of `name` (empty or starting with `.`) if it is `prefix` or a child logger of it.
+fn strip_logger_prefix<'a>(name: &'a str, prefix: &str) -> Option<&'a str> {
+    let rest = name.strip_prefix(prefix)?;
+    (rest.is_empty() || rest.starts_with('.')).then_some(rest)
+}
+
+/// Libraries (ex: tracing_subscriber::filter::Directive) expect rust-style targets like foo::bar,
+/// and may not deal well with "." as a module separator.
+fn rust_path(name: &str) -> String {
+    name.replace('.', "::")
+}
+
+/// Computes the Rust target for the Python logger `logger_name`.
+pub(crate) fn target<'a>(
+    mode: &'a TargetMode,
+    rules: &[TargetRule],
+    rust_target: &'a str,
+    logger_name: &'a str,
+) -> Cow<'a, str> {
+    let prefix = match mode {
+        TargetMode::Prefixed => Some(rust_target),
+        TargetMode::Bare => None,
+        TargetMode::Custom(prefix) => Some(prefix.as_str()),
+    };
+    let root = prefix.unwrap_or(rust_target);
+
+    if logger_name.trim().is_empty() || logger_name == "root" {
+        return Cow::Borrowed(root);
+    }
+
+    let mut name = logger_name;
+    for rule in rules {
+        match rule.apply(name) {
+            Some(Mapped::Target(target)) => return target,
+            Some(Mapped::Name(stripped)) => {
+                name = stripped;
+                break;
+            }
+            None => {}
+        }
+    }
+
+    match prefix {
+        _ if name.is_empty() => Cow::Borrowed(root),
+        Some(prefix) => Cow::Owned(format!("{prefix}::{}", rust_path(name))),
+        None => Cow::Owned(rust_path(name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(mode: &TargetMode, rules: &[TargetRule], name: &str) -> String {
+        target(mode, rules, "app", name).into_owned()
+    }
+
+    #[test]
+    fn modes() {
+        let prefixed = TargetMode::Prefixed;
+        assert_eq!(map(&prefixed, &[], "foo.bar"), "app::foo::bar");
+        assert_eq!(map(&prefixed, &[], "foo"), "app::foo");
+
+        let bare = TargetMode::Bare;
+        assert_eq!(map(&bare, &[], "foo.bar"), "foo::bar");
+        assert_eq!(map(&bare, &[], "root"), "app");
+
+        let custom = TargetMode::Custom("py".to_string());
+        assert_eq!(map(&custom, &[], "foo.bar"), "py::foo::bar");
+        assert_eq!(map(&custom, &[], "root"), "py");
+    }
+
+    #[test]
+    fn root_logger() {
+        for name in ["root", "", " "] {
+            assert_eq!(map(&TargetMode::Prefixed, &[], name), "app");
+        }
+        // Rules are not applied to the root logger.
+        let rules = [TargetRule::custom(|_| Cow::Borrowed("other"))];
+        assert_eq!(map(&TargetMode::Prefixed, &rules, "root"), "app");
+    }
+
+    #[test]
+    fn rename() {
+        let rules = [TargetRule::rename("urllib3", "http_client")];
+        for mode in [TargetMode::Prefixed, TargetMode::Bare] {
+            assert_eq!(map(&mode, &rules, "urllib3"), "http_client");
+            assert_eq!(
+                map(&mode, &rules, "urllib3.connectionpool"),
+                "http_client::connectionpool"
+            );
+        }
+        // Only whole logger name segments match.
+        assert_eq!(
+            map(&TargetMode::Prefixed, &rules, "urllib3x.pool"),
+            "app::urllib3x::pool"
+        );
+    }
+
+    #[test]
+    fn strip_prefix() {
+        let rules = [TargetRule::strip_prefix("app")];
+        assert_eq!(
+            map(&TargetMode::Prefixed, &rules, "app.worker"),
+            "app::worker"
+        );
+        assert_eq!(map(&TargetMode::Prefixed, &rules, "app"), "app");
+        assert_eq!(
+            map(&TargetMode::Prefixed, &rules, "application"),
+            "app::application"
+        );
+        assert_eq!(
+            map(&TargetMode::Bare, &rules, "app.worker.pool"),
+            "worker::pool"
+        );
+        let custom = TargetMode::Custom("py".to_string());
+        assert_eq!(map(&custom, &rules, "app.worker"), "py::worker");
+        assert_eq!(map(&custom, &rules, "app"), "py");
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn regex() {
+        let rules = [TargetRule::regex(r"^celery\.(\w+).*", "tasks::$1").unwrap()];
+        assert_eq!(
+            map(&TargetMode::Prefixed, &rules, "celery.worker.consumer"),
+            "tasks::worker"
+        );
+        assert_eq!(map(&TargetMode::Prefixed, &rules, "celery"), "app::celery");
+        assert!(TargetRule::regex("(", "").is_err());
+    }
+
+    #[test]
+    fn custom() {
+        let rules = [TargetRule::custom(|name| Cow::Owned(name.to_uppercase()))];
+        assert_eq!(map(&TargetMode::Prefixed, &rules, "foo.bar"), "FOO.BAR");
+    }
+
+    #[test]
+    fn first_match_wins() {
+        let rules = [
+            TargetRule::rename("foo.bar", "first"),
+            TargetRule::rename("foo", "second"),
+            TargetRule::strip_prefix("foo"),
+            TargetRule::custom(|_| Cow::Borrowed("fallback")),
+        ];
+        let mode = TargetMode::Prefixed;
+        assert_eq!(map(&mode, &rules, "foo.bar.baz"), "first::baz");
+        assert_eq!(map(&mode, &rules, "foo.qux"), "second::qux");
+        assert_eq!(map(&mode, &rules, "other"), "fallback");
+
+        // A strip_prefix match stops at that rule, even though later rules would match the stripped name.
+        let rules = [
+            TargetRule::strip_prefix("foo"),
+            TargetRule::custom(|_| Cow::Borrowed("fallback")),
+        ];
+        assert_eq!(map(&mode, &rules, "foo.bar"), "app::bar");
+    }
+}
@@ -0,0 +1,266 @@
+//! Owned, typed copies of the Python values attached to records as key-values.
+//!
+//! Python objects are converted once, while attached to the interpreter, into a [PyValue] that each backend
+//! can record with its native types instead of a `repr()` string.
+
+use std::fmt;
+
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyFloat, PyInt, PyList, PyString, PyTuple};
+
+/// How deeply nested containers are converted; anything deeper is kept as its `repr()`.
+///
+/// This also stops self-referencing containers, which Python prints as `[...]`.
+const MAX_DEPTH: usize = 32;
+
+/// A Python value converted to Rust.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum PyValue {
+    None,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    /// Integers outside of the `i64` and `u64` ranges, that still fit an `i128`.
+    BigInt(i128),
+    Float(f64),
+    Str(String),
+    /// A `list` or `tuple`.
+    List(Vec<PyValue>),
+    /// A `dict`, with its keys converted with `str()`.
+    Map(Vec<(String, PyValue)>),
+    /// Any other object, converted with `str()`.
+    Other(String),
+}
+
+impl PyValue {
+    pub(crate) fn from_py(value: &Bound<'_, PyAny>) -> Self {
+        Self::convert(value, 0)
+    }
+
+    fn convert(value: &Bound<'_, PyAny>, depth: usize) -> Self {
+        if value.is_none() {
+            return PyValue::None;
+        }
+        // `bool` is a subclass of `int`, so it must be checked first.
+        if let Ok(value) = value.cast::<PyBool>() {
+            return PyValue::Bool(value.is_true());
+        }
+        if value.is_instance_of::<PyInt>() {
+            if let Ok(value) = value.extract::<i64>() {
+                return PyValue::Int(value);
+            }
+            if let Ok(value) = value.extract::<u64>() {
+                return PyValue::UInt(value);
+            }
+            if let Ok(value) = value.extract::<i128>() {
+                return PyValue::BigInt(value);
+            }
+        }
+        if let Ok(value) = value.cast::<PyFloat>() {
+            return PyValue::Float(value.value());
+        }
+        if let Ok(value) = value.cast::<PyString>() {
+            return PyValue::Str(value.to_string_lossy().into_owned());
+        }
+
+        if depth < MAX_DEPTH {
+            if let Ok(dict) = value.cast::<PyDict>() {
+                return PyValue::Map(
+                    dict.iter()
+                        .map(|(key, value)| (str(&key), Self::convert(&value, depth + 1)))
+                        .collect(),
+                );
+            }
+            if let Ok(list) = value.cast::<PyList>() {
+                return PyValue::List(
+                    list.iter()
+                        .map(|item| Self::convert(&item, depth + 1))
+                        .collect(),
+                );
+            }
+            if let Ok(tuple) = value.cast::<PyTuple>() {
+                return PyValue::List(
+                    tuple
+                        .iter()
+                        .map(|item| Self::convert(&item, depth + 1))
+                        .collect(),
+                );
+            }
+        }
+
+        PyValue::Other(str(value))
+    }
+
+    /// The value as a `log` key-value, with containers recorded with `serde` with the `kv_serde` feature, or
+    /// as their JSON-like [Display] form otherwise.
+    #[cfg(feature = "kv")]
+    pub(crate) fn as_log(&self) -> log::kv::Value<'_> {
+        match self {
+            PyValue::None => log::kv::Value::null(),
+            PyValue::Bool(value) => log::kv::Value::from(*value),
+            PyValue::Int(value) => log::kv::Value::from(*value),
+            PyValue::UInt(value) => log::kv::Value::from(*value),
+            PyValue::BigInt(value) => log::kv::Value::from(*value),
+            PyValue::Float(value) => log::kv::Value::from(*value),
+            PyValue::Str(value) | PyValue::Other(value) => log::kv::Value::from(value.as_str()),
+            #[cfg(feature = "kv_serde")]
+            PyValue::List(_) | PyValue::Map(_) => log::kv::Value::from_serde(self),
+            #[cfg(not(feature = "kv_serde"))]
+            PyValue::List(_) | PyValue::Map(_) => log::kv::Value::from_display(self),
+        }
+    }
+
+    /// The value as a `tracing` field value, with containers recorded as their JSON-like [Display] form, or as
+    /// `valuable` structures with the `valuable` feature and `--cfg tracing_unstable`.
+    ///
+    /// `None` has no `tracing` equivalent and leaves the field empty.
+    #[cfg(feature = "tracing-kv")]
+    pub(crate) fn as_tracing(&self) -> Option<Box<dyn tracing::Value + '_>> {
+        Some(match self {
+            PyValue::None => return None,
+            PyValue::Bool(value) => Box::new(*value),
+            PyValue::Int(value) => Box::new(*value),
+            PyValue::UInt(value) => Box::new(*value),
+            PyValue::BigInt(value) => Box::new(*value),
+            PyValue::Float(value) => Box::new(*value),
+            PyValue::Str(value) | PyValue::Other(value) => Box::new(value.as_str()),
+            #[cfg(all(feature = "valuable", tracing_unstable))]
+            PyValue::List(_) | PyValue::Map(_) => Box::new(tracing::field::valuable(self)),
+            #[cfg(not(all(feature = "valuable", tracing_unstable)))]
+            PyValue::List(_) | PyValue::Map(_) => Box::new(tracing::field::display(self)),
+        })
+    }
+}
+
+#[cfg(feature = "valuable")]
+impl valuable::Valuable for PyValue {
+    fn as_value(&self) -> valuable::Value<'_> {
+        match self {
+            PyValue::None => valuable::Value::Unit,
+            PyValue::Bool(value) => valuable::Value::Bool(*value),
+            PyValue::Int(value) => valuable::Value::I64(*value),
+            PyValue::UInt(value) => valuable::Value::U64(*value),
+            PyValue::BigInt(value) => valuable::Value::I128(*value),
+            PyValue::Float(value) => valuable::Value::F64(*value),
+            PyValue::Str(value) | PyValue::Other(value) => valuable::Value::String(value),
+            PyValue::List(items) => valuable::Value::Listable(items),
+            PyValue::Map(_) => valuable::Value::Mappable(self),
+        }
+    }
+
+    fn visit(&self, visit: &mut dyn valuable::Visit) {
+        match self {
+            PyValue::List(items) => items.visit(visit),
+            PyValue::Map(entries) => {
+                for (key, value) in entries {
+                    visit.visit_entry(valuable::Value::String(key), value.as_value());
+                }
+            }
+            _ => visit.visit_value(self.as_value()),
+        }
+    }
+}
+
+/// Only [PyValue::Map] values are recorded as mappings, everything else is empty.
+#[cfg(feature = "valuable")]
+impl valuable::Mappable for PyValue {
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = match self {
+            PyValue::Map(entries) => entries.len(),
+            _ => 0,
+        };
+        (len, Some(len))
+    }
+}
+
+#[cfg(feature = "kv_serde")]
+impl serde::Serialize for PyValue {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::{SerializeMap, SerializeSeq};
+
+        match self {
+            PyValue::None => serializer.serialize_none(),
+            PyValue::Bool(value) => serializer.serialize_bool(*value),
+            PyValue::Int(value) => serializer.serialize_i64(*value),
+            PyValue::UInt(value) => serializer.serialize_u64(*value),
+            PyValue::BigInt(value) => serializer.serialize_i128(*value),
+            PyValue::Float(value) => serializer.serialize_f64(*value),
+            PyValue::Str(value) | PyValue::Other(value) => serializer.serialize_str(value),
+            PyValue::List(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            }
+            PyValue::Map(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+/// `str()` of a Python object, falling back to its `repr()` if `__str__` raises.
+fn str(value: &Bound<'_, PyAny>) -> String {
+    match value.str() {
+        Ok(value) => value.to_string_lossy().into_owned(),
+        Err(_) => format!("{value:?}"),
+    }
+}
+
+/// Formats the value as JSON, except for non-finite floats which are written as Rust formats them.
+impl fmt::Display for PyValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PyValue::None => f.write_str("null"),
+            PyValue::Bool(value) => write!(f, "{value}"),
+            PyValue::Int(value) => write!(f, "{value}"),
+            PyValue::UInt(value) => write!(f, "{value}"),
+            PyValue::BigInt(value) => write!(f, "{value}"),
+            // `Debug` keeps the fractional part of whole numbers, e.g. `3.0`.
+            PyValue::Float(value) => write!(f, "{value:?}"),
+            PyValue::Str(value) | PyValue::Other(value) => write_json_string(f, value),
+            PyValue::List(items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                f.write_str("]")
+            }
+            PyValue::Map(entries) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_json_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+fn write_json_string(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    f.write_str("\"")
+}
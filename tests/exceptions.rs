@@ -0,0 +1,57 @@
+//! Python exceptions attached to records.
+#![cfg(feature = "log")]
+
+use std::sync::Mutex;
+
+use pyo3::prelude::*;
+use pyo3_pylogger::PyLoggerConfig;
+
+/// Collects the messages of the records it sees.
+struct Messages(Mutex<Vec<String>>);
+
+impl log::Log for Messages {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.0.lock().unwrap().push(record.args().to_string());
+    }
+
+    fn flush(&self) {}
+}
+
+static MESSAGES: Messages = Messages(Mutex::new(Vec::new()));
+
+#[test]
+fn unprintable_exception() {
+    log::set_logger(&MESSAGES).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+
+    Python::attach(|py| {
+        PyLoggerConfig::new("app").setup_logging(py).unwrap();
+        py.run(
+            cr#"
+import logging
+
+class Unprintable(Exception):
+    def __str__(self):
+        raise ZeroDivisionError
+
+logger = logging.getLogger("exceptions")
+logger.addHandler(logging.HostHandler())
+try:
+    raise Unprintable()
+except Unprintable:
+    logger.exception("failed")
+"#,
+            None,
+            None,
+        )
+        .unwrap();
+    });
+
+    let messages = MESSAGES.0.lock().unwrap();
+    assert_eq!(messages.len(), 1);
+    assert!(messages[0].starts_with("failed"), "{}", messages[0]);
+}
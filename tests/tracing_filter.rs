@@ -0,0 +1,62 @@
+//! `tracing` filters applied to Python records.
+#![cfg(feature = "tracing-kv")]
+
+use std::sync::{Arc, Mutex};
+
+use pyo3::prelude::*;
+use pyo3_pylogger::{Backend, PyLoggerConfig};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::{EnvFilter, Layer};
+
+/// Collects the messages of the events it sees.
+#[derive(Clone, Default)]
+struct Messages(Arc<Mutex<Vec<String>>>);
+
+impl<S: tracing::Subscriber> Layer<S> for Messages {
+    fn on_event(&self, event: &tracing::Event<'_>, _: Context<'_, S>) {
+        struct Visitor<'a>(&'a mut Vec<String>);
+
+        impl tracing::field::Visit for Visitor<'_> {
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                if field.name() == "message" {
+                    self.0.push(format!("{value:?}"));
+                }
+            }
+        }
+
+        event.record(&mut Visitor(&mut self.0.lock().unwrap()));
+    }
+}
+
+#[test]
+fn field_directive_matches_extra() {
+    let messages = Messages::default();
+    let subscriber = tracing_subscriber::registry()
+        .with(EnvFilter::new("warn,app::svc[{user}]=debug"))
+        .with(messages.clone());
+    tracing::subscriber::set_global_default(subscriber).unwrap();
+
+    Python::attach(|py| {
+        PyLoggerConfig::new("app")
+            .with_backend(Backend::Tracing)
+            .setup_logging(py)
+            .unwrap();
+        py.run(
+            cr#"
+import logging
+logger = logging.getLogger("svc")
+logger.setLevel(logging.DEBUG)
+logger.addHandler(logging.HostHandler())
+logger.debug("with user", extra={"user": "bob"})
+logger.debug("without user")
+logger.debug("other key", extra={"order": 1})
+logger.warning("warning")
+"#,
+            None,
+            None,
+        )
+        .unwrap();
+    });
+
+    assert_eq!(*messages.0.lock().unwrap(), ["with user", "warning"]);
+}